@@ -1,10 +1,8 @@
 #![feature(proc_macro_mod)]
 #![feature(proc_macro_path_invoc)]
 
-extern crate trace2;
-#[macro_use]
-extern crate log;
 extern crate env_logger;
+extern crate trace2;
 
 mod implementation {
     #![::trace2::trace2]
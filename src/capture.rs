@@ -0,0 +1,289 @@
+use std::fmt;
+use std::ops::RangeBounds;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread;
+
+use format::{NamedValue, Record};
+use root::Failure;
+use sink::{self, Sink};
+use stack;
+
+/// The kind of a `TraceEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    /// A traced function is called.
+    Enter,
+
+    /// A traced function returns.
+    Exit,
+
+    /// A traced function is unwinding because of a panic.
+    Panic,
+
+    /// The recursion limit of a traced function is reached by a call, see `enter_recursion_limit`.
+    RecursionLimit,
+}
+
+/// A record captured by `capture`, with values formatted by `Debug`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// The kind of the event.
+    pub kind: EventKind,
+
+    /// Module path of the function, like `my_crate::sub`.
+    pub module_path: &'static str,
+
+    /// Name of the function, like `Foo::bar`.
+    pub fn_name: &'static str,
+
+    /// Depth of the call relative to the closure passed to `capture`, starting from 1.
+    pub depth: usize,
+
+    /// Arguments of enter events, or values of `after(..)` and `exit_fields(..)` of other events.
+    pub args: Vec<(&'static str, String)>,
+
+    /// The return value of exit events, or `<panicked>` for panic events.
+    pub ret: Option<String>,
+
+    /// The report of the latest calls of recursion limit events.
+    pub report: Option<String>,
+}
+
+impl TraceEvent {
+    fn new(kind: EventKind, record: &Record, base_depth: usize) -> Self {
+        let values = match kind {
+            EventKind::Enter | EventKind::RecursionLimit => record.args(),
+            EventKind::Exit | EventKind::Panic => record.after(),
+        };
+        TraceEvent {
+            kind,
+            module_path: record.module_path(),
+            fn_name: record.fn_name(),
+            depth: record.depth() - base_depth,
+            args: values
+                .iter()
+                .map(|&(name, value): &NamedValue| (name, format!("{:?}", value)))
+                .collect(),
+            ret: record.ret().map(|ret| format!("{:?}", ret)),
+            report: None,
+        }
+    }
+
+    /// Full path of the function, like `my_crate::sub::Foo::bar`.
+    pub fn path(&self) -> String {
+        format!("{}::{}", self.module_path, self.fn_name)
+    }
+
+    /// Whether the function is `path` or ends with `::path`, e.g. `Foo::bar` matches
+    /// `my_crate::Foo::bar`.
+    pub fn matches(&self, path: &str) -> bool {
+        let full_path = self.path();
+        full_path == path
+            || full_path.ends_with(path)
+                && full_path[..full_path.len() - path.len()].ends_with("::")
+    }
+
+    /// Path of the function without the crate name, like `sub::Foo::bar`.
+    fn relative_path(&self) -> String {
+        match self.module_path.find("::") {
+            Some(pos) => format!("{}::{}", &self.module_path[pos + 2..], self.fn_name),
+            None => self.fn_name.to_owned(),
+        }
+    }
+}
+
+/// Prints the event like a record of the default format without indent and crate name, e.g.
+/// `sub::bar(a: 1, b: 2)` or `sub::bar = 2`, or the report of a recursion limit event.
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref report) = self.report {
+            return f.write_str(report);
+        }
+        f.write_str(&self.relative_path())?;
+        if self.kind == EventKind::Enter || !self.args.is_empty() {
+            f.write_str("(")?;
+            for (i, &(name, ref value)) in self.args.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}: {}", name, value)?;
+            }
+            f.write_str(")")?;
+        }
+        if let Some(ref ret) = self.ret {
+            write!(f, " = {}", ret)?;
+        }
+        Ok(())
+    }
+}
+
+/// Render events as a tree, one event per line, indented by two spaces per depth. Paths are
+/// printed without the crate name, so that the result does not depend on where the code lives:
+///
+/// ```text
+/// foo(a: 1, b: 2)
+///   sub::bar(a: 1, b: 2)
+///   sub::bar = 2
+/// foo = ()
+/// ```
+pub fn render_tree(events: &[TraceEvent]) -> String {
+    let mut s = String::new();
+    for event in events {
+        for _ in 1..event.depth {
+            s.push_str("  ");
+        }
+        s.push_str(&event.to_string());
+        s.push('\n');
+    }
+    s
+}
+
+/// A sink that saves records of the current thread as events.
+struct CaptureSink {
+    base_depth: usize,
+    events: Mutex<Vec<TraceEvent>>,
+}
+
+impl CaptureSink {
+    fn push(&self, kind: EventKind, record: &Record) {
+        // Values are formatted before locking, since formatting may call traced functions.
+        let event = TraceEvent::new(kind, record, self.base_depth);
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Sink for CaptureSink {
+    fn on_enter(&self, record: &Record) {
+        self.push(EventKind::Enter, record);
+    }
+
+    fn on_exit(&self, record: &Record) {
+        self.push(EventKind::Exit, record);
+    }
+
+    fn on_panic(&self, record: &Record) {
+        self.push(EventKind::Panic, record);
+    }
+
+    fn on_recursion_limit(&self, record: &Record, report: &str, _panics: bool) {
+        let event = TraceEvent {
+            report: Some(report.to_owned()),
+            ..TraceEvent::new(EventKind::RecursionLimit, record, self.base_depth)
+        };
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+/// Run `f` and return events of traced calls in it on the current thread, instead of passing them
+/// to the sink set by `set_sink`. Calls in other threads are not captured.
+///
+/// If `f` panics, the panic is propagated and the events are lost.
+pub fn capture<F: FnOnce()>(f: F) -> Vec<TraceEvent> {
+    let (result, events) = capture_unwind(f);
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
+    events
+}
+
+/// Like `capture`, but also returns events captured before `f` panics.
+fn capture_unwind<R, F: FnOnce() -> R>(f: F) -> (thread::Result<R>, Vec<TraceEvent>) {
+    let sink = Rc::new(CaptureSink {
+        base_depth: stack::depth(),
+        events: Mutex::new(vec![]),
+    });
+    let result = sink::with_thread_sink(sink.clone(), || panic::catch_unwind(AssertUnwindSafe(f)));
+    let events = sink.events.lock().unwrap();
+    (result, events.clone())
+}
+
+/// Run a test function generated by `#[trace2::test]`. Events of the test are captured, and
+/// printed as a tree if the test panics or returns `Err`.
+#[doc(hidden)]
+pub fn __run_test<R: Failure, F: FnOnce() -> R>(f: F) -> R {
+    let (result, events) = capture_unwind(f);
+    match result {
+        Ok(ret) => {
+            if ret.is_failure() {
+                eprintln!("Trace of the failed test:\n{}", render_tree(&events));
+            }
+            ret
+        }
+        Err(payload) => {
+            eprintln!("Trace of the failed test:\n{}", render_tree(&events));
+            panic::resume_unwind(payload)
+        }
+    }
+}
+
+/// Run `f` and assert that traced functions matching `path` are called a number of times within
+/// `expected` on the current thread, like `expect_calls("Foo::bar", 1..=2, || ..)`, see
+/// `TraceEvent::matches`. On failure, it panics with the call tree captured by `capture`.
+pub fn expect_calls<B, F, R>(path: &str, expected: B, f: F) -> R
+where
+    B: RangeBounds<usize> + fmt::Debug,
+    F: FnOnce() -> R,
+{
+    let mut ret = None;
+    let events = capture(|| ret = Some(f()));
+    let calls = events
+        .iter()
+        .filter(|event| event.kind == EventKind::Enter && event.matches(path))
+        .count();
+    if !expected.contains(&calls) {
+        panic!(
+            "Expected {:?} calls of `{}`, but it is called {} times:\n{}",
+            expected,
+            path,
+            calls,
+            render_tree(&events)
+        );
+    }
+    ret.unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render_tree, EventKind, TraceEvent};
+
+    fn event(kind: EventKind, depth: usize, args: &[(&'static str, &str)]) -> TraceEvent {
+        TraceEvent {
+            kind,
+            module_path: "my_crate::sub",
+            fn_name: "Foo::bar",
+            depth,
+            args: args
+                .iter()
+                .map(|&(name, value)| (name, value.to_owned()))
+                .collect(),
+            ret: match kind {
+                EventKind::Enter | EventKind::RecursionLimit => None,
+                EventKind::Exit => Some("2".to_owned()),
+                EventKind::Panic => Some("<panicked>".to_owned()),
+            },
+            report: None,
+        }
+    }
+
+    #[test]
+    fn render() {
+        let events = vec![
+            event(EventKind::Enter, 1, &[("a", "1"), ("b", "\"x\"")]),
+            event(EventKind::Enter, 2, &[]),
+            event(EventKind::Panic, 2, &[]),
+            event(EventKind::Exit, 1, &[("buf", "[1]")]),
+        ];
+        assert_eq!(events[0].path(), "my_crate::sub::Foo::bar");
+        assert!(events[0].matches("Foo::bar"));
+        assert!(events[0].matches("my_crate::sub::Foo::bar"));
+        assert!(!events[0].matches("o::bar"));
+        assert!(!events[0].matches("Foo"));
+        assert_eq!(
+            render_tree(&events),
+            "sub::Foo::bar(a: 1, b: \"x\")\n  sub::Foo::bar()\n  sub::Foo::bar = <panicked>\n\
+             sub::Foo::bar(buf: [1]) = 2\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::panic::Location;
+
+use format::{self, Formatted, NamedValue, Record, RecordKind};
+use output::{self, LineWriter};
+use stack::Call;
+
+/// The entry record of the latest call, which is held back until it is known whether the call
+/// has traced child calls.
+struct PendingEnter {
+    seq: u64,
+    call: Call,
+    args: Vec<(&'static str, Formatted)>,
+    location: Option<(&'static str, u32)>,
+    caller: Option<&'static Location<'static>>,
+}
+
+impl PendingEnter {
+    fn args(&self) -> Vec<NamedValue<'_>> {
+        format::named_values(&self.args)
+    }
+
+    fn flush(self, writer: &dyn LineWriter) {
+        let args = self.args();
+        output::write_record(
+            &Record::enter_call(self.call, &args)
+                .with_locations(self.location, self.caller)
+                .with_seq(self.seq),
+            writer,
+        );
+    }
+}
+
+thread_local! {
+    static PENDING: RefCell<Option<PendingEnter>> = const { RefCell::new(None) };
+}
+
+/// Write a record, merging the entry and exit records of calls without traced child calls.
+pub(crate) fn push(record: &Record, writer: &dyn LineWriter) {
+    let pending = PENDING.with(|pending| pending.borrow_mut().take());
+    match record.kind() {
+        RecordKind::Enter => {
+            // The previous call has a child, so its entry record is written as it is.
+            if let Some(pending) = pending {
+                pending.flush(writer);
+            }
+            // Arguments are formatted now, since they might be changed by the call.
+            let args = record
+                .args()
+                .iter()
+                .map(|&(name, value)| (name, Formatted(format!("{:?}", value))))
+                .collect();
+            PENDING.with(|pending| {
+                *pending.borrow_mut() = Some(PendingEnter {
+                    seq: record.seq(),
+                    call: record.call(),
+                    args,
+                    location: record.location(),
+                    caller: record.caller(),
+                })
+            });
+        }
+        _ => match pending {
+            // Summaries of elided calls are children of the pending call, if any.
+            Some(ref pending)
+                if record.kind() != RecordKind::Elided && pending.call == record.call() =>
+            {
+                output::write_record(&Record::leaf(record, &pending.args()), writer);
+            }
+            pending => {
+                if let Some(pending) = pending {
+                    pending.flush(writer);
+                }
+                output::write_record(record, writer);
+            }
+        },
+    }
+}
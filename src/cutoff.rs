@@ -0,0 +1,117 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use stack::{self, Call};
+
+/// The global maximum depth of calls whose records are emitted. `UNLIMITED` means unlimited.
+static MAX_DEPTH: AtomicUsize = AtomicUsize::new(UNLIMITED);
+
+/// The value of `MAX_DEPTH` meaning unlimited, since `0` elides every call.
+const UNLIMITED: usize = usize::MAX;
+
+thread_local! {
+    /// Maximum depths set by calls with the `max_depth` option that have not returned, from the
+    /// outermost one. Each one is not deeper than the previous one.
+    static LIMITS: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// The call below which calls are elided and the number of them, since the last record
+    /// emitted in the current thread.
+    static ELIDED: Cell<Option<(Call, u64)>> = const { Cell::new(None) };
+}
+
+/// Set the maximum depth of calls whose records are emitted. Deeper calls are still counted in
+/// depths of other calls, and a summary like `... 1532 calls elided below depth 20` is emitted
+/// in place of their records.
+///
+/// `None` means unlimited, which is the default, and `Some(0)` elides every call. It can be lowered for calls beneath specific
+/// functions by the `max_depth` option of the attribute. Calls captured by testing helpers like
+/// `capture` are not elided.
+pub fn set_max_depth(max_depth: Option<usize>) {
+    MAX_DEPTH.store(max_depth.unwrap_or(UNLIMITED), Ordering::Relaxed);
+}
+
+/// Don't emit records of calls more than `max_depth` levels below the next traced call of the
+/// current thread, until the returned guard is dropped. It is called by code generated by
+/// `#[trace2(max_depth = N)]`.
+pub fn enter_max_depth(max_depth: usize) -> MaxDepthGuard {
+    LIMITS.with(|limits| {
+        let mut limits = limits.borrow_mut();
+        let mut limit = stack::depth() + 1 + max_depth;
+        if let Some(&outer) = limits.last() {
+            limit = limit.min(outer);
+        }
+        limits.push(limit);
+    });
+    MaxDepthGuard { _private: () }
+}
+
+/// A call with the `max_depth` option that has not returned, see `enter_max_depth`.
+#[derive(Debug)]
+pub struct MaxDepthGuard {
+    _private: (),
+}
+
+impl Drop for MaxDepthGuard {
+    fn drop(&mut self) {
+        LIMITS.with(|limits| limits.borrow_mut().pop());
+    }
+}
+
+/// The maximum depth of calls whose records are emitted in the current thread, including the
+/// limit `other` set elsewhere.
+pub(crate) fn max_depth(other: Option<usize>) -> Option<usize> {
+    let global = match MAX_DEPTH.load(Ordering::Relaxed) {
+        UNLIMITED => None,
+        max_depth => Some(max_depth),
+    };
+    let local = LIMITS.with(|limits| limits.borrow().last().cloned());
+    global.into_iter().chain(local).chain(other).min()
+}
+
+/// Count a call whose entry record is not emitted because it is deeper than `max_depth`, below
+/// the call at `max_depth`.
+pub(crate) fn elide(max_depth: usize) {
+    ELIDED.with(|elided| {
+        elided.set(match elided.get() {
+            Some((call, calls)) => Some((call, calls + 1)),
+            None => stack::call_at(max_depth).map(|call| (call, 1)),
+        })
+    });
+}
+
+/// Take the call below which calls are elided and the number of them, if any, to emit a summary
+/// before the next record.
+pub(crate) fn take_elided() -> Option<(Call, u64)> {
+    ELIDED.with(|elided| elided.take())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{elide, enter_max_depth, max_depth, take_elided};
+    use stack;
+
+    #[test]
+    fn limits() {
+        assert_eq!(max_depth(None), None);
+        assert_eq!(max_depth(Some(5)), Some(5));
+        let _outer = stack::enter("my_crate", "outer");
+        {
+            let _limit = enter_max_depth(2);
+            let _call = stack::enter("my_crate", "limited");
+            assert_eq!(max_depth(None), Some(4));
+            assert_eq!(max_depth(Some(3)), Some(3));
+            {
+                let _limit = enter_max_depth(10);
+                assert_eq!(max_depth(None), Some(4));
+            }
+        }
+        assert_eq!(max_depth(None), None);
+
+        assert_eq!(take_elided(), None);
+        let _inner = stack::enter("my_crate", "inner");
+        elide(1);
+        elide(1);
+        assert_eq!(take_elided(), Some((_outer.call(), 2)));
+        assert_eq!(take_elided(), None);
+    }
+}
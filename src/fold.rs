@@ -0,0 +1,140 @@
+use std::cell::{Cell, RefCell};
+
+use stack;
+
+/// The outermost call of a recursion of a function with the `fold_recursion` option.
+struct Fold {
+    /// Depth of the outermost call.
+    depth: usize,
+
+    /// Maximum number of nested calls of the function, including the outermost one.
+    levels: usize,
+
+    /// Number of calls of the function, including the outermost one.
+    calls: u64,
+}
+
+thread_local! {
+    /// Outermost calls of recursions in the current thread that have not returned.
+    static FOLDS: RefCell<Vec<Fold>> = const { RefCell::new(Vec::new()) };
+
+    /// Depth of the outermost recursive call in the current thread, whose records and records of
+    /// calls beneath it are folded.
+    static HIDDEN_DEPTH: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Enter a call of a function whose direct recursive calls are folded, which will be the next
+/// traced call of the current thread, until the returned guard is dropped. It is called by code
+/// generated by `#[trace2(fold_recursion)]`.
+///
+/// If the function is called directly by itself, records of the call and all calls beneath it
+/// are folded, i.e. sinks that print records skip them, see `Record::is_folded`. Instead, the exit
+/// record of the outermost call has the recursion depth and the number of calls, see
+/// `Record::recursion`.
+pub fn enter_fold(module_path: &'static str, fn_name: &'static str) -> FoldGuard {
+    let depth = stack::depth() + 1;
+    if !stack::is_current(module_path, fn_name) {
+        FOLDS.with(|folds| {
+            folds.borrow_mut().push(Fold {
+                depth,
+                levels: 1,
+                calls: 1,
+            })
+        });
+        return FoldGuard {
+            outermost: true,
+            hides: false,
+        };
+    }
+    // The caller is the outermost call or another recursive call, so the last fold is theirs.
+    FOLDS.with(|folds| {
+        if let Some(fold) = folds.borrow_mut().last_mut() {
+            fold.levels = fold.levels.max(depth + 1 - fold.depth);
+            fold.calls += 1;
+        }
+    });
+    let hides = HIDDEN_DEPTH.with(|hidden| {
+        if hidden.get().is_some() {
+            return false;
+        }
+        hidden.set(Some(depth));
+        true
+    });
+    FoldGuard {
+        outermost: false,
+        hides,
+    }
+}
+
+/// A call of a function with the `fold_recursion` option that has not returned, see `enter_fold`.
+#[derive(Debug)]
+pub struct FoldGuard {
+    outermost: bool,
+
+    /// Whether records are folded from this call.
+    hides: bool,
+}
+
+impl FoldGuard {
+    /// The recursion depth and the number of calls of the function if it is the outermost call
+    /// and has recursive calls.
+    pub(crate) fn recursion(&self) -> Option<(usize, u64)> {
+        if !self.outermost {
+            return None;
+        }
+        FOLDS.with(|folds| match folds.borrow().last() {
+            Some(fold) if fold.calls > 1 => Some((fold.levels, fold.calls)),
+            _ => None,
+        })
+    }
+}
+
+impl Drop for FoldGuard {
+    fn drop(&mut self) {
+        if self.outermost {
+            FOLDS.with(|folds| folds.borrow_mut().pop());
+        }
+        if self.hides {
+            HIDDEN_DEPTH.with(|hidden| hidden.set(None));
+        }
+    }
+}
+
+/// Whether records of a call at `depth` are folded because it is a recursive call or beneath
+/// one.
+pub(crate) fn is_hidden(depth: usize) -> bool {
+    HIDDEN_DEPTH.with(|hidden| hidden.get().is_some_and(|hidden| depth >= hidden))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{enter_fold, is_hidden};
+    use stack;
+
+    #[test]
+    fn fold() {
+        let outer = enter_fold("my_crate", "fact");
+        let _call = stack::enter("my_crate", "fact");
+        assert_eq!(outer.recursion(), None);
+        {
+            let _fold = enter_fold("my_crate", "helper");
+            let _call = stack::enter("my_crate", "helper");
+            assert!(!is_hidden(2));
+        }
+        {
+            let nested = enter_fold("my_crate", "fact");
+            let _call = stack::enter("my_crate", "fact");
+            assert_eq!(nested.recursion(), None);
+            assert!(is_hidden(2));
+            assert!(is_hidden(3));
+            let _fold = enter_fold("my_crate", "fact");
+            let _call = stack::enter("my_crate", "fact");
+        }
+        {
+            let _fold = enter_fold("my_crate", "fact");
+            let _call = stack::enter("my_crate", "fact");
+        }
+        assert!(!is_hidden(2));
+        assert_eq!(outer.recursion(), Some((3, 4)));
+    }
+}
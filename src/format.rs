@@ -0,0 +1,944 @@
+use std::fmt;
+use std::panic::Location;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use fold::FoldGuard;
+use stack::{Call, CallGuard};
+use truncate::Truncated;
+
+/// The layout of trace records, set by `set_format`.
+#[derive(Debug, Clone)]
+pub struct Format {
+    /// Template of entry records. Supported placeholders:
+    ///
+    /// - `{indent}`: Indent of the record, see `Indent`.
+    /// - `{depth}`: Depth of the call, starting from 1.
+    /// - `{path}`: Path of the function, see `PathStyle`.
+    /// - `{args}`: Arguments, like `a: 1, b: 2`.
+    /// - `{thread}`: Name of the current thread, or its ID if it has no name.
+    /// - `{thread_id}`: ID of the current thread, like `2`.
+    /// - `{seq}`: Sequence number of the record in the current thread, starting from 1.
+    /// - `{id}`: Unique ID of the call, starting from 1.
+    /// - `{parent_id}`: ID of the caller, or `0` if there is no traced caller in the current thread.
+    /// - `{location}`: ` at file:line` of the function when the `location` option is specified, or
+    ///   nothing otherwise.
+    /// - `{caller}`: ` from file:line` of the call site when the `caller` option is specified, or
+    ///   nothing otherwise.
+    pub enter: String,
+
+    /// Template of exit records. Besides placeholders of entry records:
+    ///
+    /// - `{ret}`: The return value.
+    /// - `{after}`: Values of `after(..)` and `exit_fields(..)` options in parentheses, or
+    ///   nothing if there is no such value. In exit records, `{args}` prints them without
+    ///   parentheses.
+    pub exit: String,
+
+    /// Template of single-line records of calls without traced child calls, used when `compact`
+    /// is true. It supports all placeholders of entry and exit records.
+    pub leaf: String,
+
+    /// How `{indent}` is printed.
+    pub indent: Indent,
+
+    /// How `{path}` is printed.
+    pub path: PathStyle,
+
+    /// Whether calls without traced child calls are printed in a single record, like
+    /// `foo(a: 1) = 2`. The entry record of each call is then held back per thread until its first
+    /// child call starts or it returns.
+    pub compact: bool,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format {
+            enter: "{indent}{path}({args}){location}{caller}".to_owned(),
+            exit: "{indent}{path}{after} = {ret}".to_owned(),
+            leaf: "{indent}{path}({args}){after} = {ret}{location}{caller}".to_owned(),
+            indent: Indent::default(),
+            path: PathStyle::Full,
+            compact: false,
+        }
+    }
+}
+
+/// How `{indent}` is printed.
+#[derive(Debug, Clone)]
+pub enum Indent {
+    /// Repeat a string `width` times per level, followed by a space, e.g. `>>>> ` for the first
+    /// level entry record and `<<<< ` for its exit record. Single-line records use the string of
+    /// entry records.
+    Repeat {
+        enter: String,
+        exit: String,
+        width: usize,
+    },
+
+    /// Print the depth followed by a space, e.g. `[1] `.
+    Depth,
+
+    /// Print nothing.
+    None,
+
+    /// Draw the call tree with `├─`, `│ ` and `└─` connectors at the beginning of records, like
+    /// the `tree` command. `{indent}` itself prints nothing. Records are buffered per thread until
+    /// their connectors are known, i.e. until the next sibling call starts or the parent call
+    /// returns. If `colors` is true, each depth is drawn in a different ANSI color.
+    Tree { colors: bool },
+}
+
+impl Default for Indent {
+    fn default() -> Self {
+        Indent::Repeat {
+            enter: ">".to_owned(),
+            exit: "<".to_owned(),
+            width: 4,
+        }
+    }
+}
+
+/// How `{path}` is printed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathStyle {
+    /// Module path including the crate name, e.g. `my_crate::sub::Foo::bar`.
+    Full,
+
+    /// Module path without the crate name, e.g. `sub::Foo::bar`.
+    CrateRelative,
+
+    /// No module path, e.g. `Foo::bar`.
+    Omitted,
+}
+
+/// Set the layout of trace records.
+///
+/// # Panics
+///
+/// Panics if a template contains an unknown placeholder or an unclosed `{`.
+pub fn set_format(format: Format) {
+    let parsed = ParsedFormat::new(format);
+    *FORMAT.write().unwrap() = Some(parsed);
+}
+
+static FORMAT: RwLock<Option<ParsedFormat>> = RwLock::new(None);
+
+/// Whether calls without traced child calls are printed in a single record.
+pub(crate) fn is_compact() -> bool {
+    with_format(|format| format.compact)
+}
+
+/// Returns `Some(colors)` if records are drawn as a tree, see `Indent::Tree`.
+pub(crate) fn tree_colors() -> Option<bool> {
+    with_format(|format| match format.indent {
+        Indent::Tree { colors } => Some(colors),
+        _ => None,
+    })
+}
+
+/// Call `f` with the current format. The default format is filled in first if none is set, and
+/// `f` is called under a read lock only, since it may format values that call traced functions.
+fn with_format<R, F: FnOnce(&ParsedFormat) -> R>(f: F) -> R {
+    if FORMAT.read().unwrap().is_none() {
+        FORMAT
+            .write()
+            .unwrap()
+            .get_or_insert_with(|| ParsedFormat::new(Format::default()));
+    }
+    let format = FORMAT.read().unwrap();
+    f(format.as_ref().unwrap())
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Indent,
+    Depth,
+    Path,
+    Args,
+    After,
+    Ret,
+    Thread,
+    ThreadId,
+    Seq,
+    Id,
+    ParentId,
+    Location,
+    Caller,
+}
+
+struct ParsedFormat {
+    enter: Vec<Segment>,
+    exit: Vec<Segment>,
+    leaf: Vec<Segment>,
+    indent: Indent,
+    path: PathStyle,
+    compact: bool,
+}
+
+impl ParsedFormat {
+    fn new(format: Format) -> Self {
+        ParsedFormat {
+            enter: parse_template(&format.enter),
+            exit: parse_template(&format.exit),
+            leaf: parse_template(&format.leaf),
+            indent: format.indent,
+            path: format.path,
+            compact: format.compact,
+        }
+    }
+}
+
+fn parse_template(template: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    let mut rest = template;
+    while let Some(begin) = rest.find('{') {
+        if begin > 0 {
+            segments.push(Segment::Literal(rest[..begin].to_owned()));
+        }
+        let end = rest[begin..]
+            .find('}')
+            .unwrap_or_else(|| panic!("Unclosed placeholder in template {:?}", template))
+            + begin;
+        segments.push(match &rest[begin + 1..end] {
+            "indent" => Segment::Indent,
+            "depth" => Segment::Depth,
+            "path" => Segment::Path,
+            "args" => Segment::Args,
+            "after" => Segment::After,
+            "ret" => Segment::Ret,
+            "thread" => Segment::Thread,
+            "thread_id" => Segment::ThreadId,
+            "seq" => Segment::Seq,
+            "id" => Segment::Id,
+            "parent_id" => Segment::ParentId,
+            "location" => Segment::Location,
+            "caller" => Segment::Caller,
+            p => panic!("Unknown placeholder {{{}}} in template {:?}", p, template),
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest.to_owned()));
+    }
+    segments
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RecordKind {
+    Enter,
+    Exit,
+
+    /// Both entry and exit of a call without traced child calls.
+    Leaf,
+
+    /// Exit of a call that panics.
+    Panic,
+
+    /// A summary of calls that are not emitted because they are too deep.
+    Elided,
+}
+
+/// A named value printed in a record, like an argument `("a", &1)`.
+pub type NamedValue<'a> = (&'static str, &'a dyn fmt::Debug);
+
+/// A value formatted in advance, which is printed as it is.
+pub(crate) struct Formatted(pub(crate) String);
+
+impl fmt::Debug for Formatted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Borrow formatted values as named values.
+pub(crate) fn named_values<'a>(values: &'a [(&'static str, Formatted)]) -> Vec<NamedValue<'a>> {
+    values
+        .iter()
+        .map(|&(name, ref value)| (name, value as &dyn fmt::Debug))
+        .collect()
+}
+
+/// The return value printed in records of calls that panic.
+struct Panicked;
+
+impl fmt::Debug for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("<panicked>")
+    }
+}
+
+/// A trace record, i.e. an event of a traced call that is passed to the `Sink`. It is printed
+/// according to the format set by `set_format`.
+#[derive(Clone, Copy)]
+pub struct Record<'a> {
+    kind: RecordKind,
+    seq: u64,
+    call: Call,
+    timestamp: SystemTime,
+    args: &'a [NamedValue<'a>],
+    after: &'a [NamedValue<'a>],
+    ret: Option<&'a dyn fmt::Debug>,
+    elapsed: Option<Duration>,
+    location: Option<(&'static str, u32)>,
+    caller: Option<&'static Location<'static>>,
+    thread: Option<&'a thread::Thread>,
+    elided_calls: u64,
+    recursion: Option<(usize, u64)>,
+    folded: bool,
+}
+
+impl<'a> Record<'a> {
+    /// Build an entry record.
+    pub fn enter(call: &CallGuard, args: &'a [NamedValue<'a>]) -> Self {
+        Record::enter_call(call.call(), args)
+    }
+
+    pub(crate) fn enter_call(call: Call, args: &'a [NamedValue<'a>]) -> Self {
+        Record {
+            kind: RecordKind::Enter,
+            seq: 0,
+            call,
+            timestamp: SystemTime::now(),
+            args,
+            after: &[],
+            ret: None,
+            elapsed: None,
+            location: None,
+            caller: None,
+            thread: None,
+            elided_calls: 0,
+            recursion: None,
+            folded: false,
+        }
+    }
+
+    /// Build an exit record. `after` contains values of `after(..)` and `exit_fields(..)`.
+    pub fn exit(call: &CallGuard, after: &'a [NamedValue<'a>], ret: &'a dyn fmt::Debug) -> Self {
+        Record {
+            elapsed: Some(call.elapsed()),
+            ..Record::exit_call(call.call(), after, ret)
+        }
+    }
+
+    pub(crate) fn exit_call(
+        call: Call,
+        after: &'a [NamedValue<'a>],
+        ret: &'a dyn fmt::Debug,
+    ) -> Self {
+        Record {
+            kind: RecordKind::Exit,
+            after,
+            ret: Some(ret),
+            ..Record::enter_call(call, &[])
+        }
+    }
+
+    /// Build a record of a call that is unwinding, which is printed like an exit record with
+    /// `<panicked>` as the return value.
+    pub(crate) fn panic(call: &CallGuard) -> Record<'static> {
+        Record {
+            kind: RecordKind::Panic,
+            ret: Some(&Panicked),
+            elapsed: Some(call.elapsed()),
+            ..Record::enter_call(call.call(), &[])
+        }
+    }
+
+    /// Build a summary of `calls` calls elided below the depth of `call`, which is printed like
+    /// `... 1532 calls elided below depth 20`.
+    pub(crate) fn elided(call: Call, calls: u64) -> Record<'static> {
+        Record {
+            kind: RecordKind::Elided,
+            elided_calls: calls,
+            ..Record::enter_call(call, &[])
+        }
+    }
+
+    /// Attach the source location of the traced function, i.e. `file!()` and `line!()`.
+    pub fn with_location(self, file: &'static str, line: u32) -> Self {
+        Record {
+            location: Some((file, line)),
+            ..self
+        }
+    }
+
+    /// Attach the location of the call site, i.e. `Location::caller()`.
+    pub fn with_caller(self, caller: &'static Location<'static>) -> Self {
+        Record {
+            caller: Some(caller),
+            ..self
+        }
+    }
+
+    /// Attach the recursion depth and the number of calls folded into the call, if it is the
+    /// outermost call of a recursion, see `enter_fold`.
+    pub fn with_recursion(self, fold: &FoldGuard) -> Self {
+        Record {
+            recursion: fold.recursion(),
+            ..self
+        }
+    }
+
+    /// Attach the locations of another record, see `location` and `caller`.
+    pub(crate) fn with_locations(
+        self,
+        location: Option<(&'static str, u32)>,
+        caller: Option<&'static Location<'static>>,
+    ) -> Self {
+        Record {
+            location,
+            caller,
+            ..self
+        }
+    }
+
+    /// Build a single-line record from an exit record and arguments of its entry record.
+    pub(crate) fn leaf(exit: &Record<'a>, args: &'a [NamedValue<'a>]) -> Self {
+        Record {
+            kind: RecordKind::Leaf,
+            args,
+            ..*exit
+        }
+    }
+
+    /// Attach the sequence number of the record in the current thread.
+    pub(crate) fn with_seq(&self, seq: u64) -> Self {
+        Record { seq, ..*self }
+    }
+
+    /// Mark the record as a record of a call folded into the outermost call of a recursion.
+    pub(crate) fn with_folded(&self, folded: bool) -> Self {
+        Record { folded, ..*self }
+    }
+
+    pub(crate) fn kind(&self) -> RecordKind {
+        self.kind
+    }
+
+    pub(crate) fn call(&self) -> Call {
+        self.call
+    }
+
+    /// Whether the record is printed with the exit template, i.e. it is an exit record or a record
+    /// of a call that panics.
+    pub(crate) fn is_exit(&self) -> bool {
+        match self.kind {
+            RecordKind::Exit | RecordKind::Panic => true,
+            RecordKind::Enter | RecordKind::Leaf | RecordKind::Elided => false,
+        }
+    }
+
+    /// Sequence number of the record in the current thread, starting from 1.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Unique ID of the call, starting from 1.
+    pub fn id(&self) -> u64 {
+        self.call.id
+    }
+
+    /// ID of the caller, or `0` if there is no traced caller in the current thread.
+    pub fn parent_id(&self) -> u64 {
+        self.call.parent_id
+    }
+
+    /// Depth of the call, starting from 1.
+    pub fn depth(&self) -> usize {
+        self.call.depth
+    }
+
+    /// Module path of the function, like `my_crate::sub`.
+    pub fn module_path(&self) -> &'static str {
+        self.call.module_path
+    }
+
+    /// Name of the function, like `Foo::bar`.
+    pub fn fn_name(&self) -> &'static str {
+        self.call.fn_name
+    }
+
+    /// The thread where the call happens.
+    pub fn thread(&self) -> thread::Thread {
+        match self.thread {
+            Some(thread) => thread.clone(),
+            None => thread::current(),
+        }
+    }
+
+    /// The time when the record is built.
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    /// Arguments, `self` and `fields(..)` of entry records.
+    pub fn args(&self) -> &'a [NamedValue<'a>] {
+        self.args
+    }
+
+    /// Values of `after(..)` and `exit_fields(..)` of exit records.
+    pub fn after(&self) -> &'a [NamedValue<'a>] {
+        self.after
+    }
+
+    /// The return value of exit records.
+    pub fn ret(&self) -> Option<&'a dyn fmt::Debug> {
+        self.ret
+    }
+
+    /// Duration of the call, in exit records.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+
+    /// Source location of the function with the `location` option, i.e. `(file, line)`.
+    pub fn location(&self) -> Option<(&'static str, u32)> {
+        self.location
+    }
+
+    /// Location of the call site with the `caller` option.
+    pub fn caller(&self) -> Option<&'static Location<'static>> {
+        self.caller
+    }
+
+    /// Number of calls elided below the depth of the record, in records passed to
+    /// `Sink::on_elided`.
+    pub fn elided_calls(&self) -> u64 {
+        self.elided_calls
+    }
+
+    /// The recursion depth and the number of calls of the function including the call itself, in
+    /// exit records of outermost calls of functions with the `fold_recursion` option that have
+    /// recursive calls.
+    pub fn recursion(&self) -> Option<(usize, u64)> {
+        self.recursion
+    }
+
+    /// Whether the call is a recursive call of a function with the `fold_recursion` option, or a
+    /// call beneath one, whose records are not printed by sinks that render records.
+    pub fn is_folded(&self) -> bool {
+        self.folded
+    }
+
+    /// Print the indent of a record at `depth`.
+    fn fmt_indent(
+        &self,
+        depth: usize,
+        format: &ParsedFormat,
+        f: &mut fmt::Formatter,
+    ) -> fmt::Result {
+        match format.indent {
+            Indent::Repeat {
+                ref enter,
+                ref exit,
+                width,
+            } => {
+                let s = if self.is_exit() { exit } else { enter };
+                if s.is_empty() || width == 0 {
+                    return Ok(());
+                }
+                for _ in 0..depth * width {
+                    f.write_str(s)?;
+                }
+                f.write_str(" ")
+            }
+            Indent::Depth => write!(f, "[{}] ", depth),
+            Indent::None | Indent::Tree { .. } => Ok(()),
+        }
+    }
+
+    fn fmt_path(&self, format: &ParsedFormat, f: &mut fmt::Formatter) -> fmt::Result {
+        let module_path = match format.path {
+            PathStyle::Full => self.call.module_path,
+            PathStyle::CrateRelative => match self.call.module_path.find("::") {
+                Some(pos) => &self.call.module_path[pos + 2..],
+                None => "",
+            },
+            PathStyle::Omitted => "",
+        };
+        if module_path.is_empty() {
+            f.write_str(self.call.fn_name)
+        } else {
+            write!(f, "{}::{}", module_path, self.call.fn_name)
+        }
+    }
+
+    fn render(&self, format: &ParsedFormat, f: &mut fmt::Formatter) -> fmt::Result {
+        let template = match self.kind {
+            RecordKind::Enter => &format.enter,
+            RecordKind::Exit | RecordKind::Panic => &format.exit,
+            RecordKind::Leaf => &format.leaf,
+            RecordKind::Elided => {
+                // Summaries are indented like children of the call at the cutoff depth.
+                self.fmt_indent(self.call.depth + 1, format, f)?;
+                return write!(
+                    f,
+                    "... {} calls elided below depth {}",
+                    self.elided_calls, self.call.depth
+                );
+            }
+        };
+        for segment in template.iter() {
+            match *segment {
+                Segment::Literal(ref s) => f.write_str(s)?,
+                Segment::Indent => self.fmt_indent(self.call.depth, format, f)?,
+                Segment::Depth => write!(f, "{}", self.call.depth)?,
+                Segment::Path => self.fmt_path(format, f)?,
+                Segment::Args => {
+                    if self.is_exit() {
+                        fmt_values(self.after, f)?;
+                    } else {
+                        fmt_values(self.args, f)?;
+                    }
+                }
+                Segment::After => {
+                    if !self.after.is_empty() {
+                        f.write_str("(")?;
+                        fmt_values(self.after, f)?;
+                        f.write_str(")")?;
+                    }
+                }
+                Segment::Ret => {
+                    if let Some(ret) = self.ret {
+                        write!(f, "{:?}", ret)?;
+                    }
+                }
+                Segment::Thread => fmt_thread(&self.thread(), f)?,
+                Segment::ThreadId => fmt_thread_id(&self.thread(), f)?,
+                Segment::Seq => write!(f, "{}", self.seq)?,
+                Segment::Id => write!(f, "{}", self.call.id)?,
+                Segment::ParentId => write!(f, "{}", self.call.parent_id)?,
+                Segment::Location => {
+                    if let Some((file, line)) = self.location {
+                        write!(f, " at {}:{}", file, line)?;
+                    }
+                }
+                Segment::Caller => {
+                    if let Some(caller) = self.caller {
+                        write!(f, " from {}:{}", caller.file(), caller.line())?;
+                    }
+                }
+            }
+        }
+        if let Some((depth, calls)) = self.recursion {
+            write!(f, " (recursion depth {}, {} calls)", depth, calls)?;
+        }
+        Ok(())
+    }
+}
+
+/// Print named values like `a: 1, b: 2`.
+pub(crate) fn fmt_values(values: &[NamedValue], f: &mut fmt::Formatter) -> fmt::Result {
+    for (i, &(name, value)) in values.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}: {:?}", name, value)?;
+    }
+    Ok(())
+}
+
+/// Print the name of a thread, or its ID if it has no name.
+pub(crate) fn fmt_thread(thread: &thread::Thread, f: &mut fmt::Formatter) -> fmt::Result {
+    match thread.name() {
+        Some(name) => f.write_str(name),
+        None => fmt_thread_id(thread, f),
+    }
+}
+
+/// Print the numeric part of a `ThreadId`, which has no stable accessor.
+fn fmt_thread_id(thread: &thread::Thread, f: &mut fmt::Formatter) -> fmt::Result {
+    let id = format!("{:?}", thread.id());
+    let id = id.trim_start_matches("ThreadId(").trim_end_matches(')');
+    f.write_str(id)
+}
+
+/// A record with values formatted in advance, which can be kept after the call returns.
+pub(crate) struct OwnedRecord {
+    kind: RecordKind,
+    seq: u64,
+    call: Call,
+    timestamp: SystemTime,
+    args: Vec<(&'static str, Formatted)>,
+    after: Vec<(&'static str, Formatted)>,
+    ret: Option<Formatted>,
+    elapsed: Option<Duration>,
+    location: Option<(&'static str, u32)>,
+    caller: Option<&'static Location<'static>>,
+    thread: thread::Thread,
+    elided_calls: u64,
+    recursion: Option<(usize, u64)>,
+    folded: bool,
+}
+
+impl OwnedRecord {
+    /// Format values of a record, truncated to `max_len` bytes if it is given.
+    pub(crate) fn new(record: &Record, max_len: Option<usize>) -> Self {
+        let format_values = |values: &[NamedValue]| {
+            values
+                .iter()
+                .map(|&(name, value)| {
+                    (
+                        name,
+                        Formatted(format!("{:?}", Truncated::new(value, max_len))),
+                    )
+                })
+                .collect()
+        };
+        OwnedRecord {
+            kind: record.kind,
+            seq: record.seq,
+            call: record.call,
+            timestamp: record.timestamp,
+            args: format_values(record.args),
+            after: format_values(record.after),
+            ret: record
+                .ret
+                .map(|ret| Formatted(format!("{:?}", Truncated::new(ret, max_len)))),
+            elapsed: record.elapsed,
+            location: record.location,
+            caller: record.caller,
+            thread: record.thread(),
+            elided_calls: record.elided_calls,
+            recursion: record.recursion,
+            folded: record.folded,
+        }
+    }
+
+    pub(crate) fn thread(&self) -> &thread::Thread {
+        &self.thread
+    }
+
+    /// Call `f` with the record rebuilt from formatted values.
+    pub(crate) fn with_record<R, F: FnOnce(&Record) -> R>(&self, f: F) -> R {
+        let args = named_values(&self.args);
+        let after = named_values(&self.after);
+        f(&Record {
+            kind: self.kind,
+            seq: self.seq,
+            call: self.call,
+            timestamp: self.timestamp,
+            args: &args,
+            after: &after,
+            ret: self.ret.as_ref().map(|ret| ret as &dyn fmt::Debug),
+            elapsed: self.elapsed,
+            location: self.location,
+            caller: self.caller,
+            thread: Some(&self.thread),
+            elided_calls: self.elided_calls,
+            recursion: self.recursion,
+            folded: self.folded,
+        })
+    }
+}
+
+impl<'a> fmt::Display for Record<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        with_format(|format| self.render(format, f))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        parse_template, Format, Indent, NamedValue, ParsedFormat, PathStyle, Record, Segment,
+    };
+    use stack::{self, Call};
+    use std::fmt;
+
+    fn call(depth: usize, module_path: &'static str, fn_name: &'static str) -> Call {
+        Call {
+            id: 7,
+            parent_id: 5,
+            depth,
+            module_path,
+            fn_name,
+        }
+    }
+
+    fn render(format: Format, record: &Record) -> String {
+        struct Rendered<'a, 'b: 'a>(ParsedFormat, &'a Record<'b>);
+
+        impl<'a, 'b> fmt::Display for Rendered<'a, 'b> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.1.render(&self.0, f)
+            }
+        }
+
+        Rendered(ParsedFormat::new(format), record).to_string()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            parse_template("[{depth}] {path}({args})"),
+            vec![
+                Segment::Literal("[".to_owned()),
+                Segment::Depth,
+                Segment::Literal("] ".to_owned()),
+                Segment::Path,
+                Segment::Literal("(".to_owned()),
+                Segment::Args,
+                Segment::Literal(")".to_owned()),
+            ]
+        );
+        assert_eq!(parse_template(""), vec![]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_unknown_placeholder() {
+        parse_template("{foo}");
+    }
+
+    #[test]
+    fn render_default() {
+        let enter = Record::enter_call(
+            call(2, "my_crate::sub", "Foo::bar"),
+            &[("a", &1), ("b", &"x")],
+        );
+        assert_eq!(
+            render(Format::default(), &enter),
+            ">>>>>>>> my_crate::sub::Foo::bar(a: 1, b: \"x\")"
+        );
+
+        let exit = Record::exit_call(call(2, "my_crate::sub", "Foo::bar"), &[], &3);
+        assert_eq!(
+            render(Format::default(), &exit),
+            "<<<<<<<< my_crate::sub::Foo::bar = 3"
+        );
+
+        let after: &[NamedValue] = &[("buf", &[1])];
+        let exit = Record::exit_call(call(1, "my_crate", "foo"), after, &());
+        assert_eq!(
+            render(Format::default(), &exit),
+            "<<<< my_crate::foo(buf: [1]) = ()"
+        );
+
+        let exit = Record {
+            recursion: Some((5, 9)),
+            ..Record::exit_call(call(1, "my_crate", "fib"), &[], &5)
+        };
+        assert_eq!(
+            render(Format::default(), &exit),
+            "<<<< my_crate::fib = 5 (recursion depth 5, 9 calls)"
+        );
+
+        let elided = Record::elided(call(1, "my_crate", "foo"), 12);
+        assert_eq!(
+            render(Format::default(), &elided),
+            ">>>>>>>> ... 12 calls elided below depth 1"
+        );
+
+        let guard = stack::enter("my_crate", "foo");
+        assert_eq!(
+            render(Format::default(), &Record::panic(&guard)),
+            "<<<< my_crate::foo = <panicked>"
+        );
+    }
+
+    #[test]
+    fn render_custom() {
+        let enter = Record::enter_call(call(3, "my_crate::sub", "foo"), &[("a", &1)]);
+        let exit = Record::exit_call(call(3, "my_crate", "foo"), &[], &2);
+
+        let format = Format {
+            indent: Indent::Depth,
+            path: PathStyle::CrateRelative,
+            ..Format::default()
+        };
+        assert_eq!(render(format.clone(), &enter), "[3] sub::foo(a: 1)");
+        assert_eq!(render(format, &exit), "[3] foo = 2");
+
+        let format = Format {
+            enter: "{path} <- {args}".to_owned(),
+            exit: "{path} -> {ret}".to_owned(),
+            indent: Indent::Repeat {
+                enter: "  ".to_owned(),
+                exit: "  ".to_owned(),
+                width: 1,
+            },
+            path: PathStyle::Omitted,
+            ..Format::default()
+        };
+        assert_eq!(render(format.clone(), &enter), "foo <- a: 1");
+        assert_eq!(render(format, &exit), "foo -> 2");
+    }
+
+    #[test]
+    fn render_thread() {
+        let enter = Record::enter_call(call(1, "my_crate", "foo"), &[("a", &1)]).with_seq(3);
+        let format = Format {
+            enter: "[{thread}#{seq}] {indent}{path}({args})".to_owned(),
+            ..Format::default()
+        };
+        let thread = ::std::thread::current();
+        let expected = format!("[{}#3] >>>> my_crate::foo(a: 1)", thread.name().unwrap());
+        assert_eq!(render(format, &enter), expected);
+
+        let format = Format {
+            enter: "{thread_id}".to_owned(),
+            ..Format::default()
+        };
+        let thread_id = render(format, &enter);
+        assert_eq!(
+            format!("ThreadId({})", thread_id),
+            format!("{:?}", thread.id())
+        );
+    }
+
+    #[test]
+    fn render_call_id() {
+        let enter = Record::enter_call(call(1, "my_crate", "foo"), &[("a", &1)]);
+        let format = Format {
+            enter: "{id}/{parent_id} {path}({args})".to_owned(),
+            ..Format::default()
+        };
+        assert_eq!(render(format, &enter), "7/5 my_crate::foo(a: 1)");
+    }
+
+    #[test]
+    fn render_location() {
+        let enter = Record::enter_call(call(1, "my_crate", "foo"), &[("a", &1)])
+            .with_location("src/lib.rs", 12);
+        assert_eq!(
+            render(Format::default(), &enter),
+            ">>>> my_crate::foo(a: 1) at src/lib.rs:12"
+        );
+
+        let caller = ::std::panic::Location::caller();
+        let exit = Record::exit_call(call(1, "my_crate", "foo"), &[], &2)
+            .with_location("src/lib.rs", 12)
+            .with_caller(caller);
+        assert_eq!(render(Format::default(), &exit), "<<<< my_crate::foo = 2");
+        let leaf = Record::leaf(&exit, &[("a", &1)]);
+        assert_eq!(
+            render(Format::default(), &leaf),
+            format!(
+                ">>>> my_crate::foo(a: 1) = 2 at src/lib.rs:12 from {}:{}",
+                caller.file(),
+                caller.line()
+            )
+        );
+    }
+
+    #[test]
+    fn render_leaf() {
+        let exit = Record::exit_call(call(2, "my_crate", "foo"), &[], &2);
+        let leaf = Record::leaf(&exit, &[("a", &1)]);
+        assert_eq!(
+            render(Format::default(), &leaf),
+            ">>>>>>>> my_crate::foo(a: 1) = 2"
+        );
+
+        let exit = Record::exit_call(call(1, "my_crate", "foo"), &[("b", &3)], &2);
+        let leaf = Record::leaf(&exit, &[("a", &1)]);
+        assert_eq!(
+            render(Format::default(), &leaf),
+            ">>>> my_crate::foo(a: 1)(b: 3) = 2"
+        );
+    }
+}
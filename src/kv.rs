@@ -0,0 +1,115 @@
+use log::kv::{Error, Key, Source, Value, VisitSource};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use format::{Record, RecordKind};
+
+/// Structured fields of records, attached to `log` records as key-values:
+///
+/// - `fn`: Name of the function, like `Foo::bar`.
+/// - `module`: Module path of the function.
+/// - `depth`, `id` and `parent_id`: See `Format`.
+/// - `event`: `enter`, `exit`, `panic` for calls that panic, `leaf` for single-line records of
+///   calls without traced child calls, or `elided` for summaries of calls deeper than the maximum
+///   depth, with the number of them in `calls`.
+/// - `arg.<name>`: Each argument and field of entry records, like `arg.id`, so that they don't
+///   collide with the keys above.
+/// - `after.<name>`: Each `after(..)` binding and field of exit records.
+/// - `ret`: The return value, in exit records.
+/// - `elapsed`: Duration of the call in nanoseconds, in exit records.
+/// - `recursion_depth` and `recursion_calls`: See `Record::recursion`.
+impl<'a> Source for Record<'a> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        let event = match self.kind() {
+            RecordKind::Enter => "enter",
+            RecordKind::Exit => "exit",
+            RecordKind::Leaf => "leaf",
+            RecordKind::Panic => "panic",
+            RecordKind::Elided => "elided",
+        };
+        visitor.visit_pair(Key::from_str("fn"), Value::from(self.fn_name()))?;
+        visitor.visit_pair(Key::from_str("module"), Value::from(self.module_path()))?;
+        visitor.visit_pair(Key::from_str("depth"), Value::from(self.depth()))?;
+        visitor.visit_pair(Key::from_str("id"), Value::from(self.id()))?;
+        visitor.visit_pair(Key::from_str("parent_id"), Value::from(self.parent_id()))?;
+        visitor.visit_pair(Key::from_str("event"), Value::from(event))?;
+        for &(name, value) in self.args() {
+            let key = prefixed_key("arg", name);
+            visitor.visit_pair(Key::from_str(key), Value::from_dyn_debug(value))?;
+        }
+        for &(name, value) in self.after() {
+            let key = prefixed_key("after", name);
+            visitor.visit_pair(Key::from_str(key), Value::from_dyn_debug(value))?;
+        }
+        if self.kind() == RecordKind::Elided {
+            visitor.visit_pair(Key::from_str("calls"), Value::from(self.elided_calls()))?;
+        }
+        if let Some(ret) = self.ret() {
+            visitor.visit_pair(Key::from_str("ret"), Value::from_dyn_debug(ret))?;
+        }
+        if let Some(elapsed) = self.elapsed() {
+            let nanos = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+            visitor.visit_pair(Key::from_str("elapsed"), Value::from(nanos))?;
+        }
+        if let Some((depth, calls)) = self.recursion() {
+            visitor.visit_pair(Key::from_str("recursion_depth"), Value::from(depth))?;
+            visitor.visit_pair(Key::from_str("recursion_calls"), Value::from(calls))?;
+        }
+        Ok(())
+    }
+}
+
+/// Keys like `arg.id` by prefix and name. Names come from the source of traced functions, so there
+/// are only so many of them, and keys are leaked to be used by any record.
+static PREFIXED_KEYS: Mutex<BTreeMap<(&str, &str), &str>> = Mutex::new(BTreeMap::new());
+
+/// The key of a value named `name`, like `arg.id` for the prefix `arg`.
+pub(crate) fn prefixed_key(prefix: &'static str, name: &'static str) -> &'static str {
+    PREFIXED_KEYS
+        .lock()
+        .unwrap()
+        .entry((prefix, name))
+        .or_insert_with(|| Box::leak(format!("{}.{}", prefix, name).into_boxed_str()))
+}
+
+/// An owned copy of key-values of a record, for records that are written later.
+pub(crate) struct OwnedKeyValues(Vec<(String, OwnedValue)>);
+
+enum OwnedValue {
+    U64(u64),
+    String(String),
+}
+
+impl OwnedKeyValues {
+    pub(crate) fn new(record: &Record) -> Self {
+        struct Collector(Vec<(String, OwnedValue)>);
+
+        impl<'kvs> VisitSource<'kvs> for Collector {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+                let value = match value.to_u64() {
+                    Some(v) => OwnedValue::U64(v),
+                    None => OwnedValue::String(value.to_string()),
+                };
+                self.0.push((key.as_str().to_owned(), value));
+                Ok(())
+            }
+        }
+
+        let mut collector = Collector(vec![]);
+        let _ = record.visit(&mut collector);
+        OwnedKeyValues(collector.0)
+    }
+}
+
+impl Source for OwnedKeyValues {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), Error> {
+        for (key, value) in self.0.iter() {
+            let value = match *value {
+                OwnedValue::U64(v) => Value::from(v),
+                OwnedValue::String(ref s) => Value::from(s.as_str()),
+            };
+            visitor.visit_pair(Key::from_str(key), value)?;
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,51 @@
 #![feature(use_extern_macros)]
 
+#[macro_use]
+extern crate log;
 extern crate trace2_macro;
 
-pub use trace2_macro::trace2;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 
-use std::cell::Cell;
+mod capture;
+mod compact;
+mod cutoff;
+mod fold;
+mod format;
+mod kv;
+mod output;
+mod recorder;
+mod recursion;
+mod redact;
+mod root;
+mod sink;
+mod snapshot;
+mod stack;
+#[cfg(feature = "tracing")]
+mod tracing_sink;
+mod tree;
+mod trigger;
+mod truncate;
+mod writer;
 
-thread_local! {
-    pub static FUNC_CALL_LEVEL: Cell<usize> = Cell::new(0);
-}
+#[doc(hidden)]
+pub use capture::__run_test;
+pub use capture::{capture, expect_calls, render_tree, EventKind, TraceEvent};
+pub use cutoff::{enter_max_depth, set_max_depth, MaxDepthGuard};
+pub use fold::{enter_fold, FoldGuard};
+pub use format::{set_format, Format, Indent, NamedValue, PathStyle, Record};
+pub use output::{emit, LogSink};
+pub use recorder::{dump_recent, init_flight_recorder, FlightRecorder};
+pub use recursion::{enter_recursion_limit, set_recursion_limit_panics, RecursionGuard};
+pub use redact::{set_redact_patterns, Redact, Redacted, DEFAULT_REDACT_PATTERNS};
+pub use root::{enter_root, Failure, RootGuard};
+pub use sink::{set_sink, Sink};
+#[doc(hidden)]
+pub use snapshot::{__assert_trace_file, __assert_trace_inline};
+pub use stack::{depth, enter, CallGuard};
+pub use trace2_macro::{test, trace2};
+#[cfg(feature = "tracing")]
+pub use tracing_sink::TracingSink;
+pub use trigger::{enter_trigger, set_trigger_only, TriggerGuard};
+pub use truncate::{max_len, set_max_len, Truncated};
+pub use writer::{init_file, init_stderr, WriterSink};
@@ -0,0 +1,160 @@
+use log;
+use log::kv::Source;
+use std::cell::Cell;
+use std::fmt;
+
+use compact;
+use cutoff;
+use fold;
+use format::{self, Record, RecordKind};
+use root;
+use sink::{self, Sink};
+use tree;
+use trigger;
+
+thread_local! {
+    /// Sequence number of the last emitted record in the current thread.
+    static RECORD_SEQ: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Emit a trace record to the sink, see `set_sink`. It is called by code generated by
+/// `#[trace2]`.
+pub fn emit(record: &Record) {
+    if !trigger::is_enabled(record) {
+        return;
+    }
+    let record = &record.with_folded(fold::is_hidden(record.depth()));
+    sink::with_sink(|sink, captured| {
+        if !sink.enabled(record.module_path()) {
+            return;
+        }
+        // Calls below the limit of a trigger call are not traced at all, like calls outside
+        // trigger calls, while other limits only keep logs readable, so captured calls are not
+        // elided by them.
+        let max_depth = if captured {
+            trigger::max_depth()
+        } else {
+            cutoff::max_depth(trigger::max_depth())
+        };
+        if let Some(max_depth) = max_depth {
+            if record.depth() > max_depth {
+                if record.kind() == RecordKind::Enter && !record.is_folded() {
+                    cutoff::elide(max_depth);
+                }
+                return;
+            }
+        }
+        // The summary is emitted before the next record, which is usually the exit record of
+        // the call at the cutoff depth.
+        if let Some((call, calls)) = cutoff::take_elided() {
+            emit_to(sink, &Record::elided(call, calls), captured);
+        }
+        emit_to(sink, record, captured);
+    });
+}
+
+/// Pass a record to the sink, unless it is buffered by a root call. Captured records are never
+/// buffered.
+fn emit_to(sink: &dyn Sink, record: &Record, captured: bool) {
+    let seq = RECORD_SEQ.with(|seq| {
+        seq.set(seq.get() + 1);
+        seq.get()
+    });
+    let record = record.with_seq(seq);
+    if captured || !root::buffer(&record) {
+        dispatch(sink, &record);
+    }
+}
+
+/// Pass a report of a reached recursion limit to the sink. It is neither filtered nor buffered like
+/// records, since it is an error rather than a trace.
+pub(crate) fn report_recursion_limit(record: &Record, report: &str, panics: bool) {
+    sink::with_sink(|sink, _| sink.on_recursion_limit(record, report, panics));
+}
+
+/// Pass a record emitted before to the sink, unless it is buffered by a root call again.
+pub(crate) fn replay(record: &Record) {
+    if !root::buffer(record) {
+        sink::with_sink(|sink, _| dispatch(sink, record));
+    }
+}
+
+fn dispatch(sink: &dyn Sink, record: &Record) {
+    match record.kind() {
+        RecordKind::Enter => sink.on_enter(record),
+        RecordKind::Exit | RecordKind::Leaf => sink.on_exit(record),
+        RecordKind::Panic => sink.on_panic(record),
+        RecordKind::Elided => sink.on_elided(record),
+    }
+}
+
+/// The default sink, which writes records through `log` at the `TRACE` level, using the module
+/// path of the traced function as target. Records are printed according to the format set by
+/// `set_format`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogSink;
+
+impl LineWriter for LogSink {
+    /// Write a rendered line through `log`, with structured fields of the record as key-values.
+    fn write_line(&self, target: &'static str, line: fmt::Arguments, key_values: &dyn Source) {
+        log::logger().log(
+            &log::Record::builder()
+                .args(line)
+                .key_values(key_values)
+                .level(log::Level::Trace)
+                .target(target)
+                .module_path_static(Some(target))
+                .build(),
+        );
+    }
+}
+
+impl Sink for LogSink {
+    fn enabled(&self, module_path: &str) -> bool {
+        log_enabled!(target: module_path, log::Level::Trace)
+    }
+
+    fn on_enter(&self, record: &Record) {
+        render(record, self);
+    }
+
+    fn on_exit(&self, record: &Record) {
+        render(record, self);
+    }
+
+    fn on_panic(&self, record: &Record) {
+        render(record, self);
+    }
+
+    fn on_elided(&self, record: &Record) {
+        render(record, self);
+    }
+}
+
+/// The destination of rendered records of sinks that print records according to the format,
+/// after records are merged in compact mode or buffered in tree mode.
+pub(crate) trait LineWriter {
+    /// Write a rendered line. `target` is the module path of the traced function.
+    fn write_line(&self, target: &'static str, line: fmt::Arguments, key_values: &dyn Source);
+}
+
+/// Render a record according to the format, and write resulting lines. Records of folded calls
+/// are skipped.
+pub(crate) fn render(record: &Record, writer: &dyn LineWriter) {
+    if record.is_folded() {
+        return;
+    }
+    if format::is_compact() {
+        compact::push(record, writer);
+    } else {
+        write_record(record, writer);
+    }
+}
+
+/// Write a record, either directly or through the tree buffer.
+pub(crate) fn write_record(record: &Record, writer: &dyn LineWriter) {
+    match format::tree_colors() {
+        Some(colors) => tree::push(record, colors, writer),
+        None => writer.write_line(record.module_path(), format_args!("{}", record), record),
+    }
+}
@@ -0,0 +1,380 @@
+use log;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once, RwLock, Weak};
+use std::thread;
+
+use format::{self, OwnedRecord, Record};
+use sink::{set_sink, Sink};
+
+/// Maximum length in bytes of each recorded value, unless set by `FlightRecorder::max_len`.
+const DEFAULT_MAX_LEN: usize = 256;
+
+/// A record kept by the flight recorder.
+struct Entry {
+    /// Order of the entry among entries of all threads.
+    order: u64,
+    record: OwnedRecord,
+
+    /// The report of a reached recursion limit, printed instead of the entry record of the call
+    /// that reaches it.
+    report: Option<String>,
+}
+
+impl Entry {
+    /// Print the entry according to the format, prefixed by its thread.
+    fn write_line(&self, f: &mut dyn FnMut(&'static str, fmt::Arguments)) {
+        let thread = ThreadName(self.record.thread());
+        self.record.with_record(|record| match self.report {
+            Some(ref report) => f(
+                record.module_path(),
+                format_args!("[{}] {}", thread, report),
+            ),
+            None => f(
+                record.module_path(),
+                format_args!("[{}] {}", thread, record),
+            ),
+        });
+    }
+}
+
+struct ThreadName<'a>(&'a thread::Thread);
+
+impl<'a> fmt::Display for ThreadName<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format::fmt_thread(self.0, f)
+    }
+}
+
+/// A fixed number of slots that keep the latest entries. Each slot owns the entry it points to,
+/// and entries are moved in and out by swapping pointers, so that pushing never blocks.
+struct Ring {
+    slots: Vec<AtomicPtr<Entry>>,
+    next: AtomicUsize,
+}
+
+impl Ring {
+    fn new(capacity: usize) -> Self {
+        Ring {
+            slots: (0..capacity.max(1))
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Save an entry, dropping the oldest one if the ring is full.
+    fn push(&self, entry: Entry) {
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.slots.len();
+        let old = self.slots[i].swap(Box::into_raw(Box::new(entry)), Ordering::AcqRel);
+        if !old.is_null() {
+            // SAFETY: Non-null pointers in slots come from `Box::into_raw`, and the swap moves
+            // ownership out of the slot.
+            drop(unsafe { Box::from_raw(old) });
+        }
+    }
+
+    /// Move all entries out of the ring.
+    fn take(&self) -> Vec<Entry> {
+        self.slots
+            .iter()
+            .filter_map(|slot| {
+                let entry = slot.swap(ptr::null_mut(), Ordering::AcqRel);
+                if entry.is_null() {
+                    None
+                } else {
+                    // SAFETY: See `push`.
+                    Some(*unsafe { Box::from_raw(entry) })
+                }
+            })
+            .collect()
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+/// Options of the flight recorder, see `init_flight_recorder`.
+#[derive(Debug, Clone)]
+pub struct FlightRecorder {
+    capacity: usize,
+    per_thread: bool,
+    max_len: usize,
+    file: Option<PathBuf>,
+}
+
+impl FlightRecorder {
+    /// Keep the last `capacity` records of all threads.
+    pub fn new(capacity: usize) -> Self {
+        FlightRecorder {
+            capacity,
+            per_thread: false,
+            max_len: DEFAULT_MAX_LEN,
+            file: None,
+        }
+    }
+
+    /// Keep the last `capacity` records of each thread instead, so that a busy thread does not
+    /// push records of other threads out. Records of a thread are dropped when it exits.
+    pub fn per_thread(self) -> Self {
+        FlightRecorder {
+            per_thread: true,
+            ..self
+        }
+    }
+
+    /// Truncate each recorded argument and return value to at most `max_len` bytes. It defaults
+    /// to 256.
+    pub fn max_len(self, max_len: usize) -> Self {
+        FlightRecorder { max_len, ..self }
+    }
+
+    /// Append dumped records to a file instead of writing them through `log`.
+    pub fn dump_to_file<P: AsRef<Path>>(self, path: P) -> Self {
+        FlightRecorder {
+            file: Some(path.as_ref().to_owned()),
+            ..self
+        }
+    }
+}
+
+/// The running flight recorder.
+struct Recorder {
+    /// Unique ID of the recorder, which tells apart rings of the current thread created by
+    /// recorders set before.
+    id: usize,
+    options: FlightRecorder,
+    next_order: AtomicU64,
+
+    /// The ring shared by all threads, or `None` in per-thread mode.
+    shared: Option<Ring>,
+
+    /// Rings of threads in per-thread mode.
+    rings: Mutex<Vec<Weak<Ring>>>,
+}
+
+static NEXT_RECORDER_ID: AtomicUsize = AtomicUsize::new(1);
+
+static RECORDER: RwLock<Option<Arc<Recorder>>> = RwLock::new(None);
+
+thread_local! {
+    /// The ring of the current thread in per-thread mode, with the ID of its recorder.
+    static LOCAL_RING: RefCell<Option<(usize, Arc<Ring>)>> = const { RefCell::new(None) };
+}
+
+impl Recorder {
+    fn push(&self, record: &Record, report: Option<&str>) {
+        let entry = Entry {
+            order: self.next_order.fetch_add(1, Ordering::Relaxed),
+            record: OwnedRecord::new(record, Some(self.options.max_len)),
+            report: report.map(str::to_owned),
+        };
+        match self.shared {
+            Some(ref ring) => ring.push(entry),
+            None => self.local_ring().push(entry),
+        }
+    }
+
+    /// The ring of the current thread, which is created and registered on first use.
+    fn local_ring(&self) -> Arc<Ring> {
+        LOCAL_RING.with(|local| {
+            let mut local = local.borrow_mut();
+            if let Some((id, ref ring)) = *local {
+                if id == self.id {
+                    return ring.clone();
+                }
+            }
+            let ring = Arc::new(Ring::new(self.options.capacity));
+            let mut rings = self.rings.lock().unwrap();
+            rings.retain(|ring| ring.strong_count() > 0);
+            rings.push(Arc::downgrade(&ring));
+            *local = Some((self.id, ring.clone()));
+            ring
+        })
+    }
+
+    /// Move all entries out of rings, in the order they are recorded.
+    fn take(&self) -> Vec<Entry> {
+        let mut entries = match self.shared {
+            Some(ref ring) => ring.take(),
+            None => self
+                .rings
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|ring| ring.upgrade())
+                .flat_map(|ring| ring.take())
+                .collect(),
+        };
+        entries.sort_by_key(|entry| entry.order);
+        entries
+    }
+
+    fn dump(&self) {
+        let entries = self.take();
+        match self.options.file {
+            Some(ref path) => {
+                let mut lines = String::new();
+                for entry in &entries {
+                    entry.write_line(&mut |_, line| {
+                        lines.push_str(&line.to_string());
+                        lines.push('\n');
+                    });
+                }
+                // Errors are ignored, since dumping happens in panic hooks.
+                if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                    let _ = file.write_all(lines.as_bytes());
+                }
+            }
+            None => {
+                for entry in &entries {
+                    entry.write_line(&mut |target, line| {
+                        log::logger().log(
+                            &log::Record::builder()
+                                .args(line)
+                                .level(log::Level::Error)
+                                .target(target)
+                                .module_path_static(Some(target))
+                                .build(),
+                        );
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// A sink that passes records to the flight recorder.
+struct RecorderSink(Arc<Recorder>);
+
+impl Sink for RecorderSink {
+    fn on_enter(&self, record: &Record) {
+        self.0.push(record, None);
+    }
+
+    fn on_exit(&self, record: &Record) {
+        self.0.push(record, None);
+    }
+
+    fn on_panic(&self, record: &Record) {
+        self.0.push(record, None);
+    }
+
+    fn on_elided(&self, record: &Record) {
+        self.0.push(record, None);
+    }
+
+    fn on_recursion_limit(&self, record: &Record, report: &str, _panics: bool) {
+        self.0.push(record, Some(report));
+    }
+}
+
+/// Keep the latest records in memory instead of writing them, and write them only when a thread
+/// panics or `dump_recent` is called, e.g.
+/// `init_flight_recorder(FlightRecorder::new(1000).per_thread())`.
+///
+/// Records of all functions are kept regardless of log levels. Values are formatted when they
+/// are recorded, and truncated to `FlightRecorder::max_len` bytes. Pushing a record never
+/// blocks other threads.
+///
+/// The panic hook is chained to the one set before, and records are dumped after it runs.
+pub fn init_flight_recorder(options: FlightRecorder) {
+    let recorder = Arc::new(Recorder {
+        id: NEXT_RECORDER_ID.fetch_add(1, Ordering::Relaxed),
+        shared: if options.per_thread {
+            None
+        } else {
+            Some(Ring::new(options.capacity))
+        },
+        options,
+        next_order: AtomicU64::new(0),
+        rings: Mutex::new(vec![]),
+    });
+    *RECORDER.write().unwrap() = Some(recorder.clone());
+    set_sink(RecorderSink(recorder));
+
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            dump_recent();
+        }));
+    });
+}
+
+/// Write records kept by the flight recorder through `log` at the `ERROR` level, or to the file
+/// set by `FlightRecorder::dump_to_file`, and remove them from memory. Each line is prefixed by
+/// the thread of the record, and lines are ordered by the time of records. Nothing is done if
+/// `init_flight_recorder` has not been called.
+pub fn dump_recent() {
+    let recorder = RECORDER.read().unwrap().clone();
+    if let Some(recorder) = recorder {
+        recorder.dump();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entry, Ring};
+    use format::{OwnedRecord, Record};
+    use stack::Call;
+    use std::thread;
+
+    fn entry(order: u64) -> Entry {
+        let call = Call {
+            id: order,
+            parent_id: 0,
+            depth: 1,
+            module_path: "my_crate",
+            fn_name: "foo",
+        };
+        Entry {
+            order,
+            record: OwnedRecord::new(&Record::enter_call(call, &[("a", &order)]), None),
+            report: None,
+        }
+    }
+
+    #[test]
+    fn ring() {
+        let ring = Ring::new(3);
+        assert!(ring.take().is_empty());
+        for i in 0..5 {
+            ring.push(entry(i));
+        }
+        let mut orders = ring.take().iter().map(|e| e.order).collect::<Vec<_>>();
+        orders.sort();
+        assert_eq!(orders, vec![2, 3, 4]);
+        assert!(ring.take().is_empty());
+
+        ring.push(entry(5));
+        let mut lines = vec![];
+        ring.take()[0].write_line(&mut |target, line| lines.push((target, line.to_string())));
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].0, "my_crate");
+        let prefix = format!("[{}] ", thread::current().name().unwrap());
+        assert!(lines[0].1.starts_with(&prefix));
+        assert!(lines[0].1.ends_with("my_crate::foo(a: 5)"));
+
+        let entry = Entry {
+            report: Some("Recursion limit 1 of `my_crate::foo` is reached.".to_owned()),
+            ..entry(6)
+        };
+        entry.write_line(&mut |_, line| {
+            assert_eq!(
+                line.to_string(),
+                format!("{}Recursion limit 1 of `my_crate::foo` is reached.", prefix)
+            )
+        });
+    }
+}
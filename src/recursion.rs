@@ -0,0 +1,177 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use format::{OwnedRecord, Record};
+use output;
+
+/// Number of the latest calls of a function that are printed when its recursion limit is reached.
+const RECENT_CALLS: usize = 10;
+
+/// Maximum length in bytes of each printed argument.
+const MAX_LEN: usize = 256;
+
+/// Whether reaching a recursion limit panics.
+static PANICS: AtomicBool = AtomicBool::new(true);
+
+/// Calls of a function with the `recursion_limit` option that have not returned.
+#[derive(Default)]
+struct Counter {
+    /// Number of the calls.
+    depth: usize,
+
+    /// Entry records of the latest calls close to the limit, with their depths in the counter.
+    recent: Vec<(usize, OwnedRecord)>,
+}
+
+thread_local! {
+    /// Counters of functions with the `recursion_limit` option in the current thread, by their
+    /// module paths and names.
+    static COUNTERS: RefCell<HashMap<(&'static str, &'static str), Counter>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Set whether reaching the recursion limit of a function panics after reporting its latest calls.
+/// It is `true` by default. If it is `false`, the latest calls are reported as a warning and the
+/// call goes on.
+pub fn set_recursion_limit_panics(panics: bool) {
+    PANICS.store(panics, Ordering::Relaxed);
+}
+
+/// Count a call of a function with the entry record `record` until the returned guard is
+/// dropped. It is called by code generated by `#[trace2(recursion_limit = N)]`.
+///
+/// When there are `limit` calls of the function in the current thread, a report of the latest
+/// calls with their arguments is passed to the sink, see `Sink::on_recursion_limit`, and it panics
+/// unless `set_recursion_limit_panics(false)` has been called. Arguments are only formatted for
+/// calls close to the limit.
+pub fn enter_recursion_limit(record: &Record, limit: usize) -> RecursionGuard {
+    let key = (record.module_path(), record.fn_name());
+    let depth = COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        let counter = counters.entry(key).or_default();
+        counter.depth += 1;
+        if counter.depth + RECENT_CALLS > limit {
+            counter
+                .recent
+                .push((counter.depth, OwnedRecord::new(record, Some(MAX_LEN))));
+            if counter.recent.len() > RECENT_CALLS {
+                counter.recent.remove(0);
+            }
+        }
+        counter.depth
+    });
+    // The guard is created first, so that the counter is decreased when it panics.
+    let guard = RecursionGuard { key };
+    if depth == limit {
+        let message =
+            COUNTERS.with(|counters| limit_message(&counters.borrow()[&key], record, limit));
+        let panics = PANICS.load(Ordering::Relaxed);
+        output::report_recursion_limit(record, &message, panics);
+        if panics {
+            panic!("{}", message);
+        }
+    }
+    guard
+}
+
+/// Describe a reached recursion limit with the latest calls, like
+///
+/// ```text
+/// Recursion limit 500 of `my_crate::parse` is reached. Latest calls:
+///   #499 my_crate::parse(input: "((1")
+///   #500 my_crate::parse(input: "(1")
+/// ```
+fn limit_message(counter: &Counter, record: &Record, limit: usize) -> String {
+    let mut message = format!(
+        "Recursion limit {} of `{}::{}` is reached. Latest calls:",
+        limit,
+        record.module_path(),
+        record.fn_name()
+    );
+    for &(depth, ref recent) in counter.recent.iter() {
+        recent.with_record(|record| {
+            let args = record
+                .args()
+                .iter()
+                .map(|&(name, value)| format!("{}: {:?}", name, value))
+                .collect::<Vec<_>>();
+            let _ = write!(
+                message,
+                "\n  #{} {}::{}({})",
+                depth,
+                record.module_path(),
+                record.fn_name(),
+                args.join(", ")
+            );
+        });
+    }
+    message
+}
+
+/// A call of a function with the `recursion_limit` option that has not returned, see
+/// `enter_recursion_limit`.
+#[derive(Debug)]
+pub struct RecursionGuard {
+    key: (&'static str, &'static str),
+}
+
+impl Drop for RecursionGuard {
+    fn drop(&mut self) {
+        COUNTERS.with(|counters| {
+            let mut counters = counters.borrow_mut();
+            let empty = match counters.get_mut(&self.key) {
+                Some(counter) => {
+                    if counter.recent.last().map(|&(depth, _)| depth) == Some(counter.depth) {
+                        counter.recent.pop();
+                    }
+                    counter.depth -= 1;
+                    counter.depth == 0
+                }
+                None => false,
+            };
+            if empty {
+                counters.remove(&self.key);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{enter_recursion_limit, COUNTERS};
+    use format::Record;
+    use stack::Call;
+
+    fn record(n: usize) -> Record<'static> {
+        Record::enter_call(
+            Call {
+                id: n as u64,
+                parent_id: 0,
+                depth: n,
+                module_path: "my_crate",
+                fn_name: "parse",
+            },
+            &[],
+        )
+    }
+
+    #[test]
+    fn counters() {
+        let guards = (1..16)
+            .map(|n| enter_recursion_limit(&record(n), 20))
+            .collect::<Vec<_>>();
+        COUNTERS.with(|counters| {
+            let counters = counters.borrow();
+            let counter = &counters[&("my_crate", "parse")];
+            assert_eq!(counter.depth, 15);
+            let depths = counter.recent.iter().map(|r| r.0).collect::<Vec<_>>();
+            assert_eq!(depths, vec![11, 12, 13, 14, 15]);
+        });
+        for guard in guards.into_iter().rev() {
+            drop(guard);
+        }
+        COUNTERS.with(|counters| assert!(counters.borrow().is_empty()));
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+use std::sync::RwLock;
+
+/// Name patterns of values that are redacted unless the `redact_patterns(..)` option is
+/// specified.
+pub const DEFAULT_REDACT_PATTERNS: &[&str] = &[
+    "*password*",
+    "*passwd*",
+    "*secret*",
+    "*token*",
+    "api_key",
+    "apikey",
+    "*_key",
+];
+
+/// Name patterns of values that are redacted at runtime.
+static PATTERNS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Set name patterns of arguments and fields to be printed as `***`, in addition to the ones of
+/// the attribute. `*` matches any characters, and the match is case insensitive. Passing an empty
+/// list clears the patterns.
+pub fn set_redact_patterns(patterns: &[&str]) {
+    *PATTERNS.write().unwrap() = patterns.iter().map(|p| p.to_lowercase()).collect();
+}
+
+/// Whether a value with one of the given names matches `patterns` of the attribute, or
+/// redaction patterns set at runtime.
+fn is_redacted(names: &[&str], patterns: &[&str]) -> bool {
+    let runtime_patterns = PATTERNS.read().unwrap();
+    names.iter().any(|name| {
+        let name = name.to_lowercase();
+        patterns
+            .iter()
+            .any(|pattern| wildcard_match(&pattern.to_lowercase(), &name))
+            || runtime_patterns
+                .iter()
+                .any(|pattern| wildcard_match(pattern, &name))
+    })
+}
+
+/// Match `name` against `pattern`, in which `*` matches any sequence of characters.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // The position of last `*` in pattern and the position in name it matched up to.
+    let mut backtrack = None;
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == name[n] {
+            p += 1;
+            n += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            backtrack = Some((star_p, star_n + 1));
+            p = star_p + 1;
+            n = star_n + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A value that is always printed as `***`.
+pub struct Redacted;
+
+impl fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+/// A wrapper which prints the inner value, or `***` if its name or the name of any struct field
+/// it is nested in matches redaction patterns.
+pub struct Redact<'a, T: fmt::Debug + ?Sized + 'a> {
+    names: &'static [&'static str],
+    patterns: &'static [&'static str],
+    value: &'a T,
+}
+
+impl<'a, T: fmt::Debug + ?Sized + 'a> Redact<'a, T> {
+    /// Check `names` against `DEFAULT_REDACT_PATTERNS` and patterns set by `set_redact_patterns`.
+    pub fn new(names: &'static [&'static str], value: &'a T) -> Self {
+        Self::with_patterns(names, DEFAULT_REDACT_PATTERNS, value)
+    }
+
+    /// Check `names` against `patterns` of the `redact_patterns(..)` option and patterns set by
+    /// `set_redact_patterns`.
+    pub fn with_patterns(
+        names: &'static [&'static str],
+        patterns: &'static [&'static str],
+        value: &'a T,
+    ) -> Self {
+        Redact {
+            names,
+            patterns,
+            value,
+        }
+    }
+}
+
+impl<'a, T: fmt::Debug + ?Sized + 'a> fmt::Debug for Redact<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if is_redacted(self.names, self.patterns) {
+            Redacted.fmt(f)
+        } else {
+            fmt::Debug::fmt(self.value, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{wildcard_match, Redact};
+
+    #[test]
+    fn match_wildcard() {
+        assert!(wildcard_match("abc", "abc"));
+        assert!(!wildcard_match("abc", "abcd"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("*key*", "key"));
+        assert!(wildcard_match("*key*", "api_key_id"));
+        assert!(!wildcard_match("*key*", "kye"));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a*b*c", "aXbYbZ"));
+    }
+
+    #[test]
+    fn default_patterns() {
+        let format = |names| format!("{:?}", Redact::new(names, &1));
+        assert_eq!(format(&["password"]), "***");
+        assert_eq!(format(&["apiKey"]), "***");
+        assert_eq!(format(&["client_secret"]), "***");
+        assert_eq!(format(&["signing_key"]), "***");
+        assert_eq!(format(&["pass", "password"]), "***");
+        assert_eq!(format(&["key"]), "1");
+        assert_eq!(format(&["monkey"]), "1");
+        assert_eq!(format(&["name"]), "1");
+
+        let format = |names| format!("{:?}", Redact::with_patterns(names, &["*Cred*"], &1));
+        assert_eq!(format(&["credentials"]), "***");
+        assert_eq!(format(&["password"]), "1");
+    }
+}
@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::thread;
+
+use format::{OwnedRecord, Record};
+use output;
+
+thread_local! {
+    /// Records of root calls of the current thread that have not returned, from the outermost one.
+    static BUFFERS: RefCell<Vec<Vec<OwnedRecord>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Return values that tell whether a call fails, for `#[trace2(root, on = "err")]`. It can be
+/// implemented for other return types, like status codes.
+pub trait Failure {
+    fn is_failure(&self) -> bool;
+}
+
+impl<T, E> Failure for Result<T, E> {
+    fn is_failure(&self) -> bool {
+        self.is_err()
+    }
+}
+
+/// `()` never fails, like test functions that fail by panicking.
+impl Failure for () {
+    fn is_failure(&self) -> bool {
+        false
+    }
+}
+
+/// Start buffering records of the current thread, until the returned guard is finished or
+/// dropped. It is called by code generated by `#[trace2(root)]`.
+pub fn enter_root() -> RootGuard {
+    BUFFERS.with(|buffers| buffers.borrow_mut().push(vec![]));
+    RootGuard { finished: false }
+}
+
+/// A root call whose records are buffered, see `enter_root`. If it is dropped without being
+/// finished, buffered records are emitted only if the thread is panicking.
+#[derive(Debug)]
+pub struct RootGuard {
+    finished: bool,
+}
+
+impl RootGuard {
+    /// Stop buffering, and emit buffered records if the call fails, or discard them otherwise.
+    pub fn finish(mut self, failed: bool) {
+        self.finished = true;
+        finish(failed);
+    }
+}
+
+impl Drop for RootGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            finish(thread::panicking());
+        }
+    }
+}
+
+fn finish(failed: bool) {
+    let records = BUFFERS.with(|buffers| buffers.borrow_mut().pop());
+    if !failed {
+        return;
+    }
+    for record in records.unwrap_or_default() {
+        // Records go to the buffer of the outer root call if there is one.
+        record.with_record(output::replay);
+    }
+}
+
+/// Buffer a record if it is emitted within a root call. Returns whether it is buffered.
+pub(crate) fn buffer(record: &Record) -> bool {
+    if BUFFERS.with(|buffers| buffers.borrow().is_empty()) {
+        return false;
+    }
+    // Values are formatted before borrowing, since formatting may call traced functions.
+    let record = OwnedRecord::new(record, None);
+    BUFFERS.with(|buffers| match buffers.borrow_mut().last_mut() {
+        Some(buffer) => {
+            buffer.push(record);
+            true
+        }
+        None => false,
+    })
+}
@@ -0,0 +1,88 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::RwLock;
+
+use format::Record;
+use log::Level;
+use output::LogSink;
+
+/// The destination of trace records. Records are passed to the sink set by `set_sink` on the
+/// thread where the call happens. If no sink is set, `LogSink` is used. Records of calls folded by
+/// the `fold_recursion` option are passed too, and sinks that print records should skip them,
+/// see `Record::is_folded`.
+pub trait Sink: Send + Sync {
+    /// Whether records of functions in the given module are wanted. Other methods are not called
+    /// for calls that are not enabled.
+    fn enabled(&self, _module_path: &str) -> bool {
+        true
+    }
+
+    /// Called when a traced function is called, after its arguments are evaluated.
+    fn on_enter(&self, record: &Record);
+
+    /// Called when a traced function returns.
+    fn on_exit(&self, record: &Record);
+
+    /// Called when a traced function is unwinding because of a panic. The record is like an exit
+    /// record with `<panicked>` as the return value. Nothing is done by default.
+    fn on_panic(&self, _record: &Record) {}
+
+    /// Called in place of records of calls deeper than the maximum depth, see `set_max_depth`.
+    /// The record has the depth of the cutoff, and the number of elided calls in
+    /// `Record::elided_calls`. It is called before the next record emitted in the same thread.
+    /// Nothing is done by default.
+    fn on_elided(&self, _record: &Record) {}
+
+    /// Called when the recursion limit of a function is reached, see `enter_recursion_limit`,
+    /// with the entry record of the call that reaches it and a report of the latest calls.
+    /// `panics` tells whether the call panics afterwards. It is called even if the function is not
+    /// enabled, and the report is logged through `log` at the `ERROR` level if it panics or the
+    /// `WARN` level otherwise by default.
+    fn on_recursion_limit(&self, record: &Record, report: &str, panics: bool) {
+        let level = if panics { Level::Error } else { Level::Warn };
+        log!(target: record.module_path(), level, "{}", report);
+    }
+}
+
+static SINK: RwLock<Option<Box<dyn Sink>>> = RwLock::new(None);
+
+thread_local! {
+    /// The sink of the current thread set by `with_thread_sink`, which takes precedence over the
+    /// sink set by `set_sink`.
+    static THREAD_SINK: RefCell<Option<Rc<dyn Sink>>> = RefCell::new(None);
+}
+
+/// Set the destination of trace records, replacing the previous one.
+pub fn set_sink<S: Sink + 'static>(sink: S) {
+    *SINK.write().unwrap() = Some(Box::new(sink));
+}
+
+/// Restores the previous sink of the current thread when dropped, even if `f` panics.
+struct ThreadSinkGuard(Option<Rc<dyn Sink>>);
+
+impl Drop for ThreadSinkGuard {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        THREAD_SINK.with(|sink| *sink.borrow_mut() = previous);
+    }
+}
+
+/// Pass records of calls in the current thread to `sink` while running `f`.
+pub(crate) fn with_thread_sink<R, F: FnOnce() -> R>(sink: Rc<dyn Sink>, f: F) -> R {
+    let _guard = ThreadSinkGuard(THREAD_SINK.with(|s| s.borrow_mut().replace(sink)));
+    f()
+}
+
+/// Pass the sink of the current thread to `f`, with whether it is a thread sink set by
+/// `with_thread_sink`. Thread sinks capture every call, so records passed to them are not buffered
+/// by root calls.
+pub(crate) fn with_sink<R, F: FnOnce(&dyn Sink, bool) -> R>(f: F) -> R {
+    // The sink is cloned out so that it can emit records itself, e.g. when formatting arguments.
+    if let Some(sink) = THREAD_SINK.with(|sink| sink.borrow().clone()) {
+        return f(&*sink, true);
+    }
+    match *SINK.read().unwrap() {
+        Some(ref sink) => f(&**sink, false),
+        None => f(&LogSink, false),
+    }
+}
@@ -0,0 +1,487 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use capture::{render_tree, TraceEvent};
+
+/// The environment variable which makes `assert_trace!` update snapshots instead of failing.
+const UPDATE_VAR: &str = "TRACE2_UPDATE_SNAPSHOTS";
+
+/// Assert that the call tree of traced calls in an expression matches a snapshot, i.e. the tree
+/// rendered by `render_tree` with values that vary between runs masked, see `mask`. The snapshot
+/// is either a string literal, whose common indentation and surrounding blank lines are ignored:
+///
+/// ```ignore
+/// assert_trace!(foo(1, 2), r#"
+///     foo(a: 1, b: 2)
+///       bar(a: 1, b: 2)
+///       bar = 2
+///     foo = ()
+/// "#);
+/// ```
+///
+/// or a file relative to the directory of `Cargo.toml`:
+///
+/// ```ignore
+/// assert_trace!(foo(1, 2), file = "tests/snapshots/foo.trace");
+/// ```
+///
+/// When the environment variable `TRACE2_UPDATE_SNAPSHOTS` is set to `1`, mismatched snapshots are
+/// written with the actual call tree instead of failing, including string literals in source files.
+#[macro_export]
+macro_rules! assert_trace {
+    ($expr:expr, file = $path:expr $(,)*) => {
+        $crate::__assert_trace_file(
+            &$crate::capture(|| {
+                let _ = $expr;
+            }),
+            env!("CARGO_MANIFEST_DIR"),
+            $path,
+        )
+    };
+    ($expr:expr, $snapshot:expr $(,)*) => {
+        $crate::__assert_trace_inline(
+            &$crate::capture(|| {
+                let _ = $expr;
+            }),
+            $snapshot,
+            env!("CARGO_MANIFEST_DIR"),
+            file!(),
+            line!(),
+        )
+    };
+}
+
+#[doc(hidden)]
+pub fn __assert_trace_inline(
+    events: &[TraceEvent],
+    snapshot: &str,
+    manifest_dir: &str,
+    file: &str,
+    line: u32,
+) {
+    let actual = normalize(&render_tree(events));
+    let expected = normalize(snapshot);
+    if actual == expected {
+        return;
+    }
+    let location = format!("{}:{}", file, line);
+    if !is_update_mode() {
+        mismatch(&location, &expected, &actual);
+    }
+    let path = source_path(manifest_dir, file);
+    if let Err(e) = update_inline(&path, line, &actual) {
+        panic!("Failed to update snapshot at {}: {}", location, e);
+    }
+    eprintln!("Updated snapshot at {}", location);
+}
+
+#[doc(hidden)]
+pub fn __assert_trace_file(events: &[TraceEvent], manifest_dir: &str, path: &str) {
+    let actual = normalize(&render_tree(events));
+    let full_path = Path::new(manifest_dir).join(path);
+    let expected = match fs::read_to_string(&full_path) {
+        Ok(snapshot) => Some(normalize(&snapshot)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => panic!("Failed to read snapshot {}: {}", path, e),
+    };
+    if expected.as_ref() == Some(&actual) {
+        return;
+    }
+    if !is_update_mode() {
+        match expected {
+            Some(expected) => mismatch(path, &expected, &actual),
+            None => panic!(
+                "Snapshot {} does not exist, the actual call tree is:\n{}\nSet {}=1 to write it.",
+                path, actual, UPDATE_VAR
+            ),
+        }
+    }
+    let written = full_path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&full_path, &actual));
+    if let Err(e) = written {
+        panic!("Failed to write snapshot {}: {}", path, e);
+    }
+    eprintln!("Updated snapshot {}", path);
+}
+
+fn is_update_mode() -> bool {
+    match env::var(UPDATE_VAR) {
+        Ok(value) => value == "1",
+        Err(_) => false,
+    }
+}
+
+fn mismatch(location: &str, expected: &str, actual: &str) -> ! {
+    panic!(
+        "Call tree does not match the snapshot at {}\n\nexpected:\n{}\nactual:\n{}\n\
+         Set {}=1 to update the snapshot.",
+        location, expected, actual, UPDATE_VAR
+    )
+}
+
+/// Normalize a rendered call tree or a snapshot, by removing blank lines around it, trailing
+/// spaces and common indentation of lines, and masking values that vary between runs.
+fn normalize(text: &str) -> String {
+    let lines = text
+        .lines()
+        .map(|line| line.trim_end())
+        .skip_while(|line| line.is_empty())
+        .collect::<Vec<_>>();
+    let end = lines
+        .iter()
+        .rposition(|line| !line.is_empty())
+        .map_or(0, |pos| pos + 1);
+    let lines = &lines[..end];
+    let indent = lines
+        .iter()
+        .filter(|line| !line.is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut s = String::new();
+    for line in lines {
+        if !line.is_empty() {
+            s.push_str(&mask(&line[indent..]));
+        }
+        s.push('\n');
+    }
+    s
+}
+
+/// Replace values that vary between runs and machines with placeholders:
+///
+/// - Timestamps like `2018-09-06T17:06:54Z`, and seconds of `SystemTime` and `Instant` like
+///   `tv_sec: 1536253614, tv_nsec: 6200`, with `<timestamp>`.
+/// - Durations printed by `Debug`, like `1.5ms`, with `<duration>`.
+/// - Thread IDs like `ThreadId(3)` with `ThreadId(<thread>)`.
+/// - Pointer addresses, i.e. hexadecimal numbers of at least 6 digits like `0x7ffd5c3e8a10`, with
+///   `<ptr>`.
+fn mask(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut s = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let at_word_start = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '.');
+        let masked = if at_word_start {
+            match_timestamp(&chars, i)
+                .map(|end| (end, "<timestamp>"))
+                .or_else(|| match_duration(&chars, i).map(|end| (end, "<duration>")))
+                .or_else(|| match_pointer(&chars, i).map(|end| (end, "<ptr>")))
+        } else {
+            None
+        };
+        let masked = masked.or_else(|| {
+            ["tv_sec: ", "tv_nsec: ", "ThreadId("]
+                .iter()
+                .find(|prefix| starts_with(&chars, i, prefix))
+                .and_then(|prefix| {
+                    let start = i + prefix.chars().count();
+                    let end = match_digits(&chars, start);
+                    if end == start {
+                        return None;
+                    }
+                    s.push_str(prefix);
+                    let placeholder = if prefix.starts_with("tv_") {
+                        "<timestamp>"
+                    } else {
+                        "<thread>"
+                    };
+                    Some((end, placeholder))
+                })
+        });
+        match masked {
+            Some((end, placeholder)) => {
+                s.push_str(placeholder);
+                i = end;
+            }
+            None => {
+                s.push(chars[i]);
+                i += 1;
+            }
+        }
+    }
+    s
+}
+
+fn starts_with(chars: &[char], pos: usize, prefix: &str) -> bool {
+    prefix
+        .chars()
+        .enumerate()
+        .all(|(i, c)| chars.get(pos + i) == Some(&c))
+}
+
+/// Returns the end of decimal digits starting at `pos`.
+fn match_digits(chars: &[char], pos: usize) -> usize {
+    let mut i = pos;
+    while i < chars.len() && chars[i].is_ascii_digit() {
+        i += 1;
+    }
+    i
+}
+
+fn is_word_end(chars: &[char], pos: usize) -> bool {
+    match chars.get(pos) {
+        Some(c) => !c.is_alphanumeric(),
+        None => true,
+    }
+}
+
+/// Match `YYYY-MM-DDTHH:MM:SS` with optional fractional seconds and time zone.
+fn match_timestamp(chars: &[char], pos: usize) -> Option<usize> {
+    let mut i = pos;
+    for &(digits, separator) in &[(4, '-'), (2, '-'), (2, 'T'), (2, ':'), (2, ':'), (2, ' ')] {
+        if match_digits(chars, i) != i + digits {
+            return None;
+        }
+        i += digits;
+        if separator != ' ' {
+            if chars.get(i) != Some(&separator) {
+                return None;
+            }
+            i += 1;
+        }
+    }
+    if chars.get(i) == Some(&'.') && match_digits(chars, i + 1) > i + 1 {
+        i = match_digits(chars, i + 1);
+    }
+    match chars.get(i) {
+        Some(&'Z') => i += 1,
+        Some(&'+') | Some(&'-')
+            if match_digits(chars, i + 1) == i + 3
+                && chars.get(i + 3) == Some(&':')
+                && match_digits(chars, i + 4) == i + 6 =>
+        {
+            i += 6
+        }
+        _ => {}
+    }
+    Some(i)
+}
+
+/// Match a `Duration` printed by `Debug`, like `12ns`, `1.5µs`, `3ms` or `2.000001s`.
+fn match_duration(chars: &[char], pos: usize) -> Option<usize> {
+    let mut i = match_digits(chars, pos);
+    if i == pos {
+        return None;
+    }
+    if chars.get(i) == Some(&'.') && match_digits(chars, i + 1) > i + 1 {
+        i = match_digits(chars, i + 1);
+    }
+    ["ns", "µs", "us", "ms", "s"]
+        .iter()
+        .find(|unit| starts_with(chars, i, unit) && is_word_end(chars, i + unit.chars().count()))
+        .map(|unit| i + unit.chars().count())
+}
+
+fn match_pointer(chars: &[char], pos: usize) -> Option<usize> {
+    if !starts_with(chars, pos, "0x") {
+        return None;
+    }
+    let mut i = pos + 2;
+    while i < chars.len() && chars[i].is_ascii_hexdigit() {
+        i += 1;
+    }
+    if i - pos - 2 >= 6 && is_word_end(chars, i) {
+        Some(i)
+    } else {
+        None
+    }
+}
+
+/// Lines added to source files by updated inline snapshots, as `(file, line, added lines)`, so
+/// that later assertions in the same file can be found after the file is changed.
+static INLINE_EDITS: Mutex<Vec<(PathBuf, u32, isize)>> = Mutex::new(Vec::new());
+
+/// Find the source file of `file!()`, which is relative to the workspace root rather than to the
+/// package.
+fn source_path(manifest_dir: &str, file: &str) -> PathBuf {
+    Path::new(manifest_dir)
+        .ancestors()
+        .map(|dir| dir.join(file))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(file))
+}
+
+/// Replace the snapshot literal of the `assert_trace!` at `line` of a source file.
+fn update_inline(path: &Path, line: u32, actual: &str) -> io::Result<()> {
+    let mut edits = INLINE_EDITS.lock().unwrap();
+    let source = fs::read_to_string(path)?;
+    let shift = edits
+        .iter()
+        .filter(|edit| edit.0 == path && edit.1 < line)
+        .map(|edit| edit.2)
+        .sum::<isize>();
+    let start = line_offset(&source, (line as isize + shift) as usize);
+    let range = start
+        .and_then(|start| find_snapshot_literal(&source, start))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "snapshot literal not found"))?;
+    let literal = raw_literal(actual);
+    let added = literal.lines().count() as isize - source[range.clone()].lines().count() as isize;
+    let mut source = source;
+    source.replace_range(range, &literal);
+    fs::write(path, source)?;
+    edits.push((path.to_owned(), line, added));
+    Ok(())
+}
+
+/// Returns the byte offset of a line, starting from 1.
+fn line_offset(source: &str, line: usize) -> Option<usize> {
+    if line <= 1 {
+        return Some(0);
+    }
+    source
+        .match_indices('\n')
+        .nth(line - 2)
+        .map(|(pos, _)| pos + 1)
+}
+
+/// Find the last string literal in arguments of the first `assert_trace!` after `start`.
+fn find_snapshot_literal(source: &str, start: usize) -> Option<Range<usize>> {
+    const NAME: &str = "assert_trace!";
+    let bytes = source.as_bytes();
+    let mut i = start + source[start..].find(NAME)? + NAME.len();
+    let mut depth = 0;
+    let mut last = None;
+    while i < bytes.len() {
+        let is_ident = i > 0 && (bytes[i - 1].is_ascii_alphanumeric() || bytes[i - 1] == b'_');
+        match bytes[i] {
+            b'(' | b'[' | b'{' => {
+                depth += 1;
+                i += 1;
+            }
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                i += 1;
+                if depth == 0 {
+                    return last;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |pos| i + pos);
+            }
+            b'"' => {
+                let end = skip_string(bytes, i)?;
+                if depth == 1 {
+                    last = Some(i..end);
+                }
+                i = end;
+            }
+            b'r' if !is_ident && skip_raw_string(bytes, i).is_some() => {
+                let end = skip_raw_string(bytes, i)?;
+                if depth == 1 {
+                    last = Some(i..end);
+                }
+                i = end;
+            }
+            b'\'' if bytes.get(i + 1) == Some(&b'\\') => {
+                i = i + 2 + source[i + 2..].find('\'')? + 1;
+            }
+            b'\'' if bytes.get(i + 2) == Some(&b'\'') => i += 3,
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Returns the end of the string literal starting at `pos`.
+fn skip_string(bytes: &[u8], pos: usize) -> Option<usize> {
+    let mut i = pos + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Returns the end of the raw string literal starting at `pos`, if there is one.
+fn skip_raw_string(bytes: &[u8], pos: usize) -> Option<usize> {
+    let mut i = pos + 1;
+    while bytes.get(i) == Some(&b'#') {
+        i += 1;
+    }
+    if bytes.get(i) != Some(&b'"') {
+        return None;
+    }
+    let mut terminator = vec![b'"'];
+    terminator.extend(&bytes[pos + 1..i]);
+    bytes[i + 1..]
+        .windows(terminator.len())
+        .position(|window| window == &terminator[..])
+        .map(|p| i + 1 + p + terminator.len())
+}
+
+/// Build a raw string literal of a snapshot, starting and ending with a line break.
+fn raw_literal(snapshot: &str) -> String {
+    let mut hashes = "#".to_owned();
+    while snapshot.contains(&format!("\"{}", hashes)) {
+        hashes.push('#');
+    }
+    format!("r{}\"\n{}\"{}", hashes, snapshot, hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{find_snapshot_literal, line_offset, mask, normalize, raw_literal};
+
+    #[test]
+    fn mask_values() {
+        assert_eq!(
+            mask("at 2018-09-06T17:06:54.123Z, 2018-09-06T17:06:54+08:00, 2018-09-06"),
+            "at <timestamp>, <timestamp>, 2018-09-06"
+        );
+        assert_eq!(
+            mask("Instant { tv_sec: 1536253614, tv_nsec: 6200 }"),
+            "Instant { tv_sec: <timestamp>, tv_nsec: <timestamp> }"
+        );
+        assert_eq!(
+            mask("[12ns, 1.5µs, 3ms, 2.000001s, 10s2, ms, 1.5]"),
+            "[<duration>, <duration>, <duration>, <duration>, 10s2, ms, 1.5]"
+        );
+        assert_eq!(
+            mask("ThreadId(3) ThreadId(x)"),
+            "ThreadId(<thread>) ThreadId(x)"
+        );
+        assert_eq!(
+            mask("0x7ffd5c3e8a10 0xff a0x7ffd5c3e8a10"),
+            "<ptr> 0xff a0x7ffd5c3e8a10"
+        );
+    }
+
+    #[test]
+    fn normalize_snapshot() {
+        assert_eq!(
+            normalize("\n    foo(a: 1)  \n      bar = 2ms\n\n    foo = ()\n  "),
+            "foo(a: 1)\n  bar = <duration>\n\nfoo = ()\n"
+        );
+        assert_eq!(normalize("foo()\nfoo = ()\n"), "foo()\nfoo = ()\n");
+        assert_eq!(normalize("\n"), "");
+    }
+
+    #[test]
+    fn find_literal() {
+        let source = "fn f() {\n    let c = '(';\n    assert_trace!(foo(\")\", ')', r\"(\"), \
+                      // \"x\"\n r#\"\n  foo = \"1\"\n\"#);\n    assert_trace!(bar(), \"\");\n}\n";
+        let start = line_offset(source, 3).unwrap();
+        let range = find_snapshot_literal(source, start).unwrap();
+        assert_eq!(&source[range], "r#\"\n  foo = \"1\"\n\"#");
+        let start = line_offset(source, 7).unwrap();
+        let range = find_snapshot_literal(source, start).unwrap();
+        assert_eq!(&source[range], "\"\"");
+        assert_eq!(line_offset(source, 20), None);
+    }
+
+    #[test]
+    fn build_raw_literal() {
+        assert_eq!(raw_literal("foo()\n"), "r#\"\nfoo()\n\"#");
+        assert_eq!(raw_literal("foo(a: \"#\")\n"), "r##\"\nfoo(a: \"#\")\n\"##");
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use format::Record;
+use output;
+
+/// The ID of the next traced call. IDs are unique across threads.
+static NEXT_CALL_ID: AtomicU64 = AtomicU64::new(1);
+
+/// A traced call that has not returned.
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    id: u64,
+    module_path: &'static str,
+    fn_name: &'static str,
+}
+
+thread_local! {
+    /// Traced calls of the current thread that have not returned, from the outermost one.
+    static CALL_STACK: RefCell<Vec<Frame>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Enter a traced call, by pushing it to the call stack of the current thread. The call is left
+/// when the returned guard is dropped, even if the call panics, in which case a record of the
+/// panic is emitted. It is called by code generated by `#[trace2]`.
+pub fn enter(module_path: &'static str, fn_name: &'static str) -> CallGuard {
+    let id = NEXT_CALL_ID.fetch_add(1, Ordering::Relaxed);
+    CALL_STACK.with(|stack| {
+        let mut stack = stack.borrow_mut();
+        let parent_id = stack.last().map_or(0, |frame| frame.id);
+        stack.push(Frame {
+            id,
+            module_path,
+            fn_name,
+        });
+        CallGuard {
+            call: Call {
+                id,
+                parent_id,
+                depth: stack.len(),
+                module_path,
+                fn_name,
+            },
+            start: Instant::now(),
+        }
+    })
+}
+
+/// Depth of the current traced call, i.e. the number of traced calls of the current thread that
+/// have not returned. It is `0` if there is no such call.
+pub fn depth() -> usize {
+    CALL_STACK.with(|stack| stack.borrow().len())
+}
+
+/// Identity of the traced call at `depth` of the current thread, if it has not returned.
+pub(crate) fn call_at(depth: usize) -> Option<Call> {
+    CALL_STACK.with(|stack| {
+        let stack = stack.borrow();
+        let frame = stack.get(depth.checked_sub(1)?)?;
+        Some(Call {
+            id: frame.id,
+            parent_id: depth
+                .checked_sub(2)
+                .and_then(|parent| stack.get(parent))
+                .map_or(0, |parent| parent.id),
+            depth,
+            module_path: frame.module_path,
+            fn_name: frame.fn_name,
+        })
+    })
+}
+
+/// Whether the current traced call is a call of the given function, i.e. the next traced call of
+/// the function in the current thread will be a direct recursive call.
+pub(crate) fn is_current(module_path: &str, fn_name: &str) -> bool {
+    CALL_STACK.with(|stack| {
+        stack
+            .borrow()
+            .last()
+            .is_some_and(|frame| frame.module_path == module_path && frame.fn_name == fn_name)
+    })
+}
+
+/// Identity of a traced call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Call {
+    /// The unique ID of the call, starting from 1.
+    pub id: u64,
+    /// The ID of the caller, or `0` if there is no traced caller in the current thread.
+    pub parent_id: u64,
+    /// The depth of the call, starting from 1.
+    pub depth: usize,
+    pub module_path: &'static str,
+    pub fn_name: &'static str,
+}
+
+/// A traced call that has not returned, see `enter`.
+#[derive(Debug)]
+pub struct CallGuard {
+    call: Call,
+    start: Instant,
+}
+
+impl CallGuard {
+    /// The unique ID of the call, starting from 1.
+    pub fn id(&self) -> u64 {
+        self.call.id
+    }
+
+    /// The ID of the caller, or `0` if there is no traced caller in the current thread.
+    pub fn parent_id(&self) -> u64 {
+        self.call.parent_id
+    }
+
+    /// The depth of the call, starting from 1.
+    pub fn depth(&self) -> usize {
+        self.call.depth
+    }
+
+    /// Time elapsed since the call started.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub(crate) fn call(&self) -> Call {
+        self.call
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            output::emit(&Record::panic(self));
+        }
+        CALL_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            debug_assert_eq!(stack.last().map(|frame| frame.id), Some(self.call.id));
+            stack.pop();
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{depth, enter, is_current};
+
+    #[test]
+    fn call_stack() {
+        assert_eq!(depth(), 0);
+        let outer = enter("my_crate", "outer");
+        assert_eq!(outer.depth(), 1);
+        assert!(is_current("my_crate", "outer"));
+        assert!(!is_current("my_crate::sub", "outer"));
+        assert_eq!(outer.parent_id(), 0);
+        {
+            let inner = enter("my_crate", "inner");
+            assert_eq!(inner.depth(), 2);
+            assert_eq!(inner.parent_id(), outer.id());
+            assert!(inner.id() > outer.id());
+            assert_eq!(depth(), 2);
+        }
+        let sibling = enter("my_crate", "sibling");
+        assert_eq!(sibling.depth(), 2);
+        assert_eq!(sibling.parent_id(), outer.id());
+        drop(sibling);
+        drop(outer);
+        assert_eq!(depth(), 0);
+    }
+}
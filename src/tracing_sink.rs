@@ -0,0 +1,275 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+
+use format::{self, NamedValue, Record};
+use kv;
+use sink::Sink;
+use tracing::callsite::{self, Callsite, Identifier};
+use tracing::dispatcher;
+use tracing::field::{self, FieldSet, Value};
+use tracing::metadata::Kind;
+use tracing::span::EnteredSpan;
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Span};
+
+thread_local! {
+    /// Entered spans of traced calls of the current thread that have not returned, with IDs of
+    /// the calls.
+    static SPANS: RefCell<Vec<(u64, EnteredSpan)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Fields of spans before fields of arguments.
+const SPAN_FIELDS: [&str; 4] = ["fn", "module", "id", "parent_id"];
+
+/// Fields of spans after fields of arguments.
+const SPAN_RECORDED_FIELDS: [&str; 4] = ["location", "caller", "after", "ret"];
+
+/// Fields of events of elided calls.
+const ELIDED_FIELDS: [&str; 3] = ["message", "calls", "depth"];
+
+/// Fields of events of reached recursion limits.
+const RECURSION_LIMIT_FIELDS: [&str; 1] = ["message"];
+
+/// Callsites of spans of traced functions and events of elided calls or reached recursion limits,
+/// by module path and name.
+/// They are registered when they are first used, and leaked like callsites of `tracing` macros,
+/// which are statics.
+static CALLSITES: Mutex<BTreeMap<(&str, &str), Vec<&TraceCallsite>>> = Mutex::new(BTreeMap::new());
+
+/// The callsite of spans of a traced function, or of events of elided calls or reached recursion
+/// limits in a module, whose target is the module path.
+struct TraceCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+}
+
+impl Callsite for TraceCallsite {
+    fn set_interest(&self, _: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'_> {
+        self.metadata.get().unwrap()
+    }
+}
+
+/// The metadata of spans or events named `name` in `module_path` with the given level and fields,
+/// registered the first time.
+fn metadata(
+    module_path: &'static str,
+    name: &'static str,
+    kind: Kind,
+    level: Level,
+    fields: &[&'static str],
+) -> &'static Metadata<'static> {
+    let mut callsites = CALLSITES.lock().unwrap();
+    let callsites = callsites.entry((module_path, name)).or_default();
+    let found = callsites.iter().find(|callsite| {
+        let metadata = callsite.metadata();
+        *metadata.level() == level
+            && metadata
+                .fields()
+                .iter()
+                .map(|f| f.name())
+                .eq(fields.iter().cloned())
+    });
+    if let Some(callsite) = found {
+        return callsite.metadata.get().unwrap();
+    }
+    let callsite: &'static TraceCallsite = Box::leak(Box::new(TraceCallsite {
+        metadata: OnceLock::new(),
+    }));
+    let names: &'static [&'static str] = Box::leak(fields.to_vec().into_boxed_slice());
+    let metadata = Metadata::new(
+        name,
+        module_path,
+        level,
+        None,
+        None,
+        Some(module_path),
+        FieldSet::new(names, Identifier(callsite)),
+        kind,
+    );
+    let _ = callsite.metadata.set(metadata);
+    callsite::register(callsite);
+    callsites.push(callsite);
+    callsite.metadata.get().unwrap()
+}
+
+/// Whether the current subscriber wants spans or events with the metadata.
+fn is_enabled(metadata: &Metadata) -> bool {
+    *metadata.level() <= ::tracing::level_filters::STATIC_MAX_LEVEL
+        && *metadata.level() <= ::tracing::level_filters::LevelFilter::current()
+        && dispatcher::get_default(|d| d.enabled(metadata))
+}
+
+/// Named values printed like `a: 1, b: 2`.
+struct Values<'a>(&'a [NamedValue<'a>]);
+
+impl<'a> fmt::Display for Values<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format::fmt_values(self.0, f)
+    }
+}
+
+/// A sink that creates a [tracing] span at the `TRACE` level for each traced call. Set it by
+/// `set_sink(TracingSink)` to pass records to the subscriber instead of `log`.
+///
+/// The span is named after the function and entered until the call returns. Its target and
+/// module path are the module path of the function, so subscribers can filter spans by module.
+/// It has the fields `fn`, `module`, `id` and `parent_id` of the call, each argument prefixed
+/// like `arg.id`, as well as `location` and `caller` when they are printed by options. `after`
+/// printed like `a: 1, b: 2` and `ret` are recorded when the call returns. Calls elided below the
+/// maximum depth are reported by an event with `calls`, and folded calls have no spans. Reached
+/// recursion limits are reported by an event at the `ERROR` or `WARN` level.
+///
+/// [tracing]: https://docs.rs/tracing/
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingSink;
+
+impl TracingSink {
+    /// Record values of the exit record to the span of the call and exit the span.
+    fn exit(&self, record: &Record) {
+        if record.is_folded() {
+            return;
+        }
+        // Spans of inner calls whose exit records are not emitted are exited too. They are
+        // dropped after the stack is released, since subscribers may call traced functions.
+        let spans = SPANS.with(|spans| {
+            let mut spans = spans.borrow_mut();
+            match spans.iter().rposition(|&(id, _)| id == record.id()) {
+                Some(pos) => spans.split_off(pos),
+                None => vec![],
+            }
+        });
+        if let Some((_, span)) = spans.first() {
+            if !record.after().is_empty() {
+                span.record("after", field::display(Values(record.after())));
+            }
+            if let Some(ret) = record.ret() {
+                span.record("ret", field::debug(ret));
+            }
+        }
+        for (_, span) in spans.into_iter().rev() {
+            drop(span);
+        }
+    }
+}
+
+impl Sink for TracingSink {
+    fn on_enter(&self, record: &Record) {
+        if record.is_folded() {
+            return;
+        }
+        let fields = SPAN_FIELDS
+            .iter()
+            .cloned()
+            .chain(
+                record
+                    .args()
+                    .iter()
+                    .map(|&(name, _)| kv::prefixed_key("arg", name)),
+            )
+            .chain(SPAN_RECORDED_FIELDS.iter().cloned())
+            .collect::<Vec<_>>();
+        let metadata = metadata(
+            record.module_path(),
+            record.fn_name(),
+            Kind::SPAN,
+            Level::TRACE,
+            &fields,
+        );
+        if !is_enabled(metadata) {
+            return;
+        }
+        let fields = metadata.fields();
+        let mut iter = fields.iter();
+        let span = Span::new(
+            metadata,
+            &fields.value_set(&[
+                (&iter.next().unwrap(), Some(&record.fn_name() as &dyn Value)),
+                (
+                    &iter.next().unwrap(),
+                    Some(&record.module_path() as &dyn Value),
+                ),
+                (&iter.next().unwrap(), Some(&record.id() as &dyn Value)),
+                (
+                    &iter.next().unwrap(),
+                    Some(&record.parent_id() as &dyn Value),
+                ),
+            ]),
+        );
+        for (&(_, value), field) in record.args().iter().zip(iter) {
+            span.record(&field, field::debug(value));
+        }
+        if let Some((file, line)) = record.location() {
+            span.record(
+                "location",
+                field::display(format_args!("{}:{}", file, line)),
+            );
+        }
+        if let Some(caller) = record.caller() {
+            span.record("caller", field::display(caller));
+        }
+        let span = span.entered();
+        SPANS.with(|spans| spans.borrow_mut().push((record.id(), span)));
+    }
+
+    fn on_exit(&self, record: &Record) {
+        self.exit(record);
+    }
+
+    fn on_panic(&self, record: &Record) {
+        self.exit(record);
+    }
+
+    fn on_elided(&self, record: &Record) {
+        let metadata = metadata(
+            record.module_path(),
+            "elided",
+            Kind::EVENT,
+            Level::TRACE,
+            &ELIDED_FIELDS,
+        );
+        if !is_enabled(metadata) {
+            return;
+        }
+        let fields = metadata.fields();
+        let mut iter = fields.iter();
+        Event::dispatch(
+            metadata,
+            &fields.value_set(&[
+                (
+                    &iter.next().unwrap(),
+                    Some(&format_args!("calls elided below the maximum depth") as &dyn Value),
+                ),
+                (
+                    &iter.next().unwrap(),
+                    Some(&record.elided_calls() as &dyn Value),
+                ),
+                (&iter.next().unwrap(), Some(&record.depth() as &dyn Value)),
+            ]),
+        );
+    }
+
+    fn on_recursion_limit(&self, record: &Record, report: &str, panics: bool) {
+        let level = if panics { Level::ERROR } else { Level::WARN };
+        let metadata = metadata(
+            record.module_path(),
+            "recursion_limit",
+            Kind::EVENT,
+            level,
+            &RECURSION_LIMIT_FIELDS,
+        );
+        if !is_enabled(metadata) {
+            return;
+        }
+        let fields = metadata.fields();
+        Event::dispatch(
+            metadata,
+            &fields.value_set(&[(
+                &fields.iter().next().unwrap(),
+                Some(&format_args!("{}", report) as &dyn Value),
+            )]),
+        );
+    }
+}
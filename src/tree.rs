@@ -0,0 +1,202 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use format::{Record, RecordKind};
+use kv::OwnedKeyValues;
+use output::LineWriter;
+
+/// ANSI colors of each depth, used cyclically.
+const COLORS: &[&str] = &["36", "32", "33", "35", "34", "31"];
+
+/// Whether a call is the last child of its parent, or `None` if it is not known yet.
+type LastFlag = Rc<Cell<Option<bool>>>;
+
+/// A call that has not returned.
+struct Node {
+    /// Last flags of the call and its ancestors, excluding the root call. They decide the
+    /// connectors of records of the call.
+    columns: Vec<LastFlag>,
+
+    /// The last flag of the latest child call.
+    last_child: Option<LastFlag>,
+}
+
+/// A rendered record waiting for its connectors to be known.
+struct Line {
+    target: &'static str,
+    columns: Vec<LastFlag>,
+
+    /// Whether the innermost column is drawn as a connector, i.e. the line is an entry record.
+    connector: bool,
+    text: String,
+    key_values: OwnedKeyValues,
+}
+
+impl Line {
+    fn is_ready(&self) -> bool {
+        self.columns.iter().all(|last| last.get().is_some())
+    }
+
+    fn render(&self, colors: bool) -> String {
+        let mut s = String::new();
+        for (i, last) in self.columns.iter().enumerate() {
+            let last = last.get().unwrap();
+            let column = match (self.connector && i + 1 == self.columns.len(), last) {
+                (true, false) => "├─ ",
+                (true, true) => "└─ ",
+                (false, false) => "│  ",
+                (false, true) => "   ",
+            };
+            // Column `i` belongs to calls at depth `i + 2`.
+            Self::push_colored(&mut s, column, i + 1, colors);
+        }
+        Self::push_colored(&mut s, &self.text, self.columns.len(), colors);
+        s
+    }
+
+    fn push_colored(s: &mut String, text: &str, color: usize, colors: bool) {
+        if colors {
+            let _ = write!(s, "\x1b[{}m{}\x1b[0m", COLORS[color % COLORS.len()], text);
+        } else {
+            s.push_str(text);
+        }
+    }
+}
+
+/// Records of the current thread that are not written yet.
+#[derive(Default)]
+struct TreeBuffer {
+    stack: Vec<Node>,
+    lines: VecDeque<Line>,
+}
+
+impl TreeBuffer {
+    /// Buffer a rendered record, and return all buffered lines whose connectors are known.
+    fn push(&mut self, record: &Record, text: String, key_values: OwnedKeyValues) -> Vec<Line> {
+        if !record.is_exit() {
+            let mut columns = vec![];
+            if let Some(parent) = self.stack.last_mut() {
+                let last = Rc::new(Cell::new(None));
+                // The previous sibling is not the last child anymore.
+                if let Some(sibling) = parent.last_child.replace(last.clone()) {
+                    sibling.set(Some(false));
+                }
+                columns = parent.columns.clone();
+                columns.push(last);
+            }
+            self.lines.push_back(Line {
+                target: record.module_path(),
+                columns: columns.clone(),
+                connector: true,
+                text,
+                key_values,
+            });
+            // Single-line records have no child and no exit record.
+            if record.kind() == RecordKind::Enter {
+                self.stack.push(Node {
+                    columns,
+                    last_child: None,
+                });
+            }
+        } else {
+            let columns = match self.stack.pop() {
+                Some(node) => {
+                    if let Some(child) = node.last_child {
+                        child.set(Some(true));
+                    }
+                    node.columns
+                }
+                None => vec![],
+            };
+            self.lines.push_back(Line {
+                target: record.module_path(),
+                columns,
+                connector: false,
+                text,
+                key_values,
+            });
+        }
+
+        let mut ready = vec![];
+        while self.lines.front().is_some_and(|line| line.is_ready()) {
+            ready.push(self.lines.pop_front().unwrap());
+        }
+        ready
+    }
+}
+
+thread_local! {
+    static BUFFER: RefCell<TreeBuffer> = RefCell::new(TreeBuffer::default());
+}
+
+/// Buffer a record, and write all records whose connectors are known.
+pub(crate) fn push(record: &Record, colors: bool, writer: &dyn LineWriter) {
+    // Values are formatted and lines are written while the buffer is not borrowed, since `Debug`
+    // implementations and loggers may call traced functions.
+    let text = record.to_string();
+    let key_values = OwnedKeyValues::new(record);
+    let ready = BUFFER.with(|buffer| buffer.borrow_mut().push(record, text, key_values));
+    for line in ready {
+        writer.write_line(
+            line.target,
+            format_args!("{}", line.render(colors)),
+            &line.key_values,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TreeBuffer;
+    use format::Record;
+    use kv::OwnedKeyValues;
+    use stack::Call;
+
+    fn call(depth: usize, fn_name: &'static str) -> Call {
+        Call {
+            id: 0,
+            parent_id: 0,
+            depth,
+            module_path: "my_crate",
+            fn_name,
+        }
+    }
+
+    #[test]
+    fn connectors() {
+        let mut buffer = TreeBuffer::default();
+        let mut lines = vec![];
+        {
+            let mut push = |record: &Record, text: &str| {
+                let ready = buffer.push(record, text.to_owned(), OwnedKeyValues::new(record));
+                lines.extend(ready.iter().map(|line| line.render(false)));
+            };
+            push(&Record::enter_call(call(1, "run"), &[]), "run()");
+            push(&Record::enter_call(call(2, "fib"), &[]), "fib(n: 1)");
+            push(&Record::enter_call(call(3, "leaf"), &[]), "leaf()");
+            push(&Record::exit_call(call(3, "leaf"), &[], &()), "leaf = ()");
+            let exit = Record::exit_call(call(3, "one"), &[], &1);
+            push(&Record::leaf(&exit, &[]), "one() = 1");
+            push(&Record::exit_call(call(2, "fib"), &[], &1), "fib = 1");
+            push(&Record::enter_call(call(2, "fib"), &[]), "fib(n: 0)");
+            push(&Record::exit_call(call(2, "fib"), &[], &0), "fib = 0");
+            push(&Record::exit_call(call(1, "run"), &[], &1), "run = 1");
+        }
+        assert_eq!(
+            lines,
+            vec![
+                "run()",
+                "├─ fib(n: 1)",
+                "│  ├─ leaf()",
+                "│  │  leaf = ()",
+                "│  └─ one() = 1",
+                "│  fib = 1",
+                "└─ fib(n: 0)",
+                "   fib = 0",
+                "run = 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,115 @@
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use format::{Record, RecordKind};
+use stack;
+
+/// Whether records are only emitted within trigger calls.
+static TRIGGER_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// A trigger call that has not returned.
+struct Trigger {
+    /// Depth of the trigger call.
+    depth: usize,
+
+    /// Maximum number of levels below the trigger call whose records are emitted.
+    max_depth: Option<usize>,
+}
+
+thread_local! {
+    /// Trigger calls of the current thread that have not returned, from the outermost one.
+    static TRIGGERS: RefCell<Vec<Trigger>> = const { RefCell::new(Vec::new()) };
+
+    /// `TRIGGER_ONLY` when the outermost traced call of the current thread started.
+    static THREAD_TRIGGER_ONLY: Cell<bool> = Cell::new(TRIGGER_ONLY.load(Ordering::Relaxed));
+}
+
+/// Set whether records are only emitted while a function marked with `#[trace2(trigger)]` is
+/// running in the current thread. It is `false` by default, which means all traced functions emit
+/// records outside trigger calls, so it must be enabled for `trigger` to have any effect:
+///
+/// ```ignore
+/// #[trace2::trace2(trigger)]
+/// fn handle(request: Request) -> Response { .. }
+///
+/// fn main() {
+///     trace2::set_trigger_only(true);
+///     serve(handle);
+/// }
+/// ```
+///
+/// Each thread applies it from its next outermost traced call, so calls that have not returned
+/// still emit either both or none of their records.
+pub fn set_trigger_only(enabled: bool) {
+    TRIGGER_ONLY.store(enabled, Ordering::Relaxed);
+}
+
+/// Enter a trigger call, which will be the next traced call of the current thread, until the
+/// returned guard is dropped. Records of calls more than `max_depth` levels below it are not
+/// emitted. It is called by code generated by `#[trace2(trigger)]`.
+pub fn enter_trigger(max_depth: Option<usize>) -> TriggerGuard {
+    TRIGGERS.with(|triggers| {
+        triggers.borrow_mut().push(Trigger {
+            depth: stack::depth() + 1,
+            max_depth,
+        })
+    });
+    TriggerGuard { _private: () }
+}
+
+/// A trigger call that has not returned, see `enter_trigger`.
+#[derive(Debug)]
+pub struct TriggerGuard {
+    _private: (),
+}
+
+impl Drop for TriggerGuard {
+    fn drop(&mut self) {
+        TRIGGERS.with(|triggers| triggers.borrow_mut().pop());
+    }
+}
+
+/// Whether a record is emitted, i.e. the current thread is running a trigger call or records are
+/// not only emitted within trigger calls.
+pub(crate) fn is_enabled(record: &Record) -> bool {
+    if record.kind() == RecordKind::Enter && record.depth() == 1 {
+        THREAD_TRIGGER_ONLY.with(|only| only.set(TRIGGER_ONLY.load(Ordering::Relaxed)));
+    }
+    TRIGGERS.with(|triggers| !triggers.borrow().is_empty()) || !THREAD_TRIGGER_ONLY.with(Cell::get)
+}
+
+/// The maximum depth of calls whose records are emitted, according to the innermost trigger call.
+pub(crate) fn max_depth() -> Option<usize> {
+    TRIGGERS.with(|triggers| {
+        triggers
+            .borrow()
+            .last()
+            .and_then(|trigger| trigger.max_depth.map(|max_depth| trigger.depth + max_depth))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{enter_trigger, is_enabled, max_depth};
+    use format::Record;
+    use stack;
+
+    #[test]
+    fn trigger() {
+        assert_eq!(max_depth(), None);
+        let outer = stack::enter("my_crate", "outer");
+        assert!(is_enabled(&Record::enter(&outer, &[])));
+        {
+            let _trigger = enter_trigger(Some(1));
+            let call = stack::enter("my_crate", "trigger");
+            assert!(is_enabled(&Record::enter(&call, &[])));
+            assert_eq!(max_depth(), Some(3));
+            {
+                let _trigger = enter_trigger(None);
+                assert_eq!(max_depth(), None);
+            }
+            assert_eq!(max_depth(), Some(3));
+        }
+        assert_eq!(max_depth(), None);
+    }
+}
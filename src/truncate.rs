@@ -0,0 +1,164 @@
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The global maximum length of a formatted value. `UNLIMITED` means unlimited.
+static MAX_LEN: AtomicUsize = AtomicUsize::new(UNLIMITED);
+
+/// The value of `MAX_LEN` meaning unlimited, since `0` truncates every value.
+const UNLIMITED: usize = usize::MAX;
+
+/// Set the maximum length in bytes of each formatted argument and return value.
+///
+/// `None` means unlimited, which is the default. It can be overridden for specific functions by
+/// the `max_len` option of the attribute.
+pub fn set_max_len(max_len: Option<usize>) {
+    MAX_LEN.store(max_len.unwrap_or(UNLIMITED), Ordering::Relaxed);
+}
+
+/// Get the maximum length in bytes of each formatted argument and return value.
+pub fn max_len() -> Option<usize> {
+    match MAX_LEN.load(Ordering::Relaxed) {
+        UNLIMITED => None,
+        max_len => Some(max_len),
+    }
+}
+
+/// Number of bytes counted past the limit to print how many bytes are truncated. Formatting stops
+/// when more bytes are left, so huge values are never formatted in full.
+const MAX_COUNTED: usize = 64 * 1024;
+
+/// A wrapper whose `Debug` output is the `Debug` output of the inner value truncated to
+/// `max_len` bytes. Truncated values are marked with `…(+N bytes)`, or `…(+N bytes or more)` if
+/// more than 64 KiB are left. The alternate flag, i.e. `{:#?}`, is passed to the inner value.
+pub struct Truncated<'a, T: fmt::Debug + ?Sized + 'a> {
+    value: &'a T,
+    max_len: Option<usize>,
+}
+
+impl<'a, T: fmt::Debug + ?Sized + 'a> Truncated<'a, T> {
+    pub fn new(value: &'a T, max_len: Option<usize>) -> Self {
+        Truncated { value, max_len }
+    }
+}
+
+impl<'a, T: fmt::Debug + ?Sized + 'a> fmt::Debug for Truncated<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let max_len = match self.max_len {
+            None => return fmt::Debug::fmt(self.value, f),
+            Some(max_len) => max_len,
+        };
+        let mut writer = BoundedWriter::new(max_len);
+        let result = if f.alternate() {
+            write!(writer, "{:#?}", self.value)
+        } else {
+            write!(writer, "{:?}", self.value)
+        };
+        f.write_str(&writer.buf)?;
+        if writer.skipped > MAX_COUNTED {
+            write!(f, "…(+{} bytes or more)", writer.skipped)
+        } else if writer.skipped > 0 {
+            result?;
+            write!(f, "…(+{} bytes)", writer.skipped)
+        } else {
+            result
+        }
+    }
+}
+
+/// A `fmt::Write` adapter that keeps at most `max_len` bytes. Bytes past the limit are only
+/// counted, and once more than `MAX_COUNTED` of them are counted, it returns an error, so that
+/// the rest of the value is not formatted at all.
+struct BoundedWriter {
+    buf: String,
+    max_len: usize,
+    skipped: usize,
+}
+
+impl BoundedWriter {
+    fn new(max_len: usize) -> Self {
+        BoundedWriter {
+            buf: String::new(),
+            max_len,
+            skipped: 0,
+        }
+    }
+}
+
+impl fmt::Write for BoundedWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.skipped > 0 {
+            self.skipped += s.len();
+        } else {
+            let remaining = self.max_len - self.buf.len();
+            if s.len() <= remaining {
+                self.buf.push_str(s);
+                return Ok(());
+            }
+            // Never cut in the middle of a char.
+            let mut end = remaining;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.buf.push_str(&s[..end]);
+            self.skipped = s.len() - end;
+        }
+        if self.skipped > MAX_COUNTED {
+            return Err(fmt::Error);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Truncated;
+    use std::fmt;
+
+    #[test]
+    fn truncate() {
+        let v = vec![1u8; 100];
+        assert_eq!(
+            format!("{:?}", Truncated::new(&v, None)),
+            format!("{:?}", v)
+        );
+        assert_eq!(
+            format!("{:?}", Truncated::new(&v, Some(1000))),
+            format!("{:?}", v)
+        );
+        assert_eq!(
+            format!("{:?}", Truncated::new(&v, Some(8))),
+            "[1, 1, 1…(+292 bytes)"
+        );
+        assert_eq!(
+            format!("{:#?}", Truncated::new(&[1, 2], Some(8))),
+            "[\n    1,…(+9 bytes)"
+        );
+
+        let s = "中文";
+        assert_eq!(format!("{:?}", Truncated::new(s, Some(3))), "\"…(+7 bytes)");
+        assert_eq!(
+            format!("{:?}", Truncated::new(s, Some(4))),
+            "\"中…(+4 bytes)"
+        );
+    }
+
+    #[test]
+    fn stop_early() {
+        /// A value that fails the test if it is formatted in full.
+        struct Endless;
+
+        impl fmt::Debug for Endless {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for _ in 0..10_000 {
+                    f.write_str("0123456789")?;
+                }
+                panic!("formatted in full");
+            }
+        }
+
+        assert_eq!(
+            format!("{:?}", Truncated::new(&Endless, Some(15))),
+            "012345678901234…(+65545 bytes or more)"
+        );
+    }
+}
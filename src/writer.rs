@@ -0,0 +1,177 @@
+use log::kv::Source;
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use format::Record;
+use output::{self, LineWriter};
+use sink::{set_sink, Sink};
+
+/// Buffered lines are written once they exceed this size in bytes, even if calls have not
+/// returned.
+const BUFFER_LIMIT: usize = 8 * 1024;
+
+/// A writer shared by clones of a `WriterSink`.
+type SharedWriter = Arc<Mutex<Box<dyn Write + Send>>>;
+
+/// Rendered lines of the current thread that are not written yet, with the writer of the sink
+/// that rendered them.
+#[derive(Default)]
+struct LineBuffer {
+    lines: String,
+    writer: Option<SharedWriter>,
+
+    /// Depth of the outermost call whose entry record is passed to a `WriterSink` since lines
+    /// were last written. Lines are written when it returns.
+    outermost: Option<usize>,
+}
+
+impl LineBuffer {
+    fn take(&mut self) -> Option<(String, SharedWriter)> {
+        let writer = self.writer.take()?;
+        Some((mem::take(&mut self.lines), writer))
+    }
+}
+
+impl Drop for LineBuffer {
+    /// Write remaining lines when the thread exits, e.g. if records of its outermost traced call
+    /// were not emitted.
+    fn drop(&mut self) {
+        if let Some((lines, writer)) = self.take() {
+            write_lines(&writer, &lines);
+        }
+    }
+}
+
+thread_local! {
+    static BUFFER: RefCell<LineBuffer> = RefCell::new(LineBuffer::default());
+}
+
+/// Write lines at once.
+fn write_lines(writer: &SharedWriter, lines: &str) {
+    if lines.is_empty() {
+        return;
+    }
+    // Errors are ignored like in loggers, since tracing must not affect the traced program.
+    let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = writer.write_all(lines.as_bytes());
+    let _ = writer.flush();
+}
+
+/// A sink that writes records to an `io::Write` without a logger, one record per line printed
+/// according to the format set by `set_format`.
+///
+/// Lines are buffered per thread, and written when the outermost call of the thread whose
+/// records are passed to the sink returns, e.g. a trigger call beneath other traced calls, the
+/// buffer grows large, the thread exits or `flush` is called. Each write contains
+/// whole lines, so lines of different threads never mix. Clones of the sink share the writer, so
+/// a clone can be kept to call `flush` after the sink is set by `set_sink`.
+#[derive(Clone)]
+pub struct WriterSink {
+    writer: SharedWriter,
+}
+
+impl WriterSink {
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        WriterSink {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Write lines buffered in the current thread, e.g. before a long-running traced call
+    /// returns. Lines buffered in other threads are not written.
+    pub fn flush(&self) {
+        if let Some((lines, writer)) = BUFFER.with(|buffer| buffer.borrow_mut().take()) {
+            write_lines(&writer, &lines);
+        }
+    }
+
+    fn push(&self, record: &Record) {
+        output::render(record, self);
+        let flush = BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+            let outermost = buffer.outermost.get_or_insert(record.depth());
+            let returned = record.is_exit() && record.depth() <= *outermost;
+            if returned {
+                buffer.outermost = None;
+            } else if !record.is_exit() && record.depth() < *outermost {
+                *outermost = record.depth();
+            }
+            returned || buffer.lines.len() >= BUFFER_LIMIT
+        });
+        if flush {
+            self.flush();
+        }
+    }
+}
+
+impl fmt::Debug for WriterSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriterSink").finish()
+    }
+}
+
+impl LineWriter for WriterSink {
+    fn write_line(&self, _target: &'static str, line: fmt::Arguments, _key_values: &dyn Source) {
+        // The line is formatted before the buffer is borrowed, since `Debug` implementations of
+        // values may call traced functions.
+        let mut text = line.to_string();
+        text.push('\n');
+        let previous = BUFFER.with(|buffer| {
+            let mut buffer = buffer.borrow_mut();
+            // Lines rendered by another sink are written to their own writer first.
+            let previous = match buffer.writer {
+                Some(ref writer) if !Arc::ptr_eq(writer, &self.writer) => buffer.take(),
+                _ => None,
+            };
+            buffer.writer = Some(self.writer.clone());
+            buffer.lines.push_str(&text);
+            previous
+        });
+        if let Some((lines, writer)) = previous {
+            write_lines(&writer, &lines);
+        }
+    }
+}
+
+impl Sink for WriterSink {
+    fn on_enter(&self, record: &Record) {
+        self.push(record);
+    }
+
+    fn on_exit(&self, record: &Record) {
+        self.push(record);
+    }
+
+    fn on_panic(&self, record: &Record) {
+        self.push(record);
+    }
+
+    fn on_elided(&self, record: &Record) {
+        self.push(record);
+    }
+
+    /// Write the report after lines buffered in the current thread at once, since the call may
+    /// panic.
+    fn on_recursion_limit(&self, _record: &Record, report: &str, _panics: bool) {
+        self.flush();
+        write_lines(&self.writer, &format!("{}\n", report));
+    }
+}
+
+/// Write records to stderr without a logger, see `WriterSink`.
+pub fn init_stderr() {
+    set_sink(WriterSink::new(io::stderr()));
+}
+
+/// Write records to a file without a logger, see `WriterSink`. The file is created if it does
+/// not exist, and records are appended to it.
+pub fn init_file<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    set_sink(WriterSink::new(file));
+    Ok(())
+}
@@ -0,0 +1,89 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+#[derive(Debug)]
+struct Counter {
+    count: i32,
+}
+
+#[::trace2::trace2(after(self))]
+impl Counter {
+    fn incr(&mut self, delta: i32) -> i32 {
+        self.count += delta;
+        self.count
+    }
+
+    fn get(&self) -> i32 {
+        self.count
+    }
+
+    fn into_count(self) -> i32 {
+        self.count
+    }
+}
+
+#[::trace2::trace2(after(name, n))]
+fn consume(name: String, n: u32) -> usize {
+    let name = name;
+    name.len() + n as usize
+}
+
+#[::trace2::trace2(after(buf))]
+fn fill(buf: &mut Vec<u8>, n: u8) {
+    for i in 0..n {
+        buf.push(i);
+    }
+}
+
+#[::trace2::trace2(after(buf, missing))]
+fn fill_early_return(buf: &mut Vec<u8>) -> usize {
+    buf.push(1);
+    if !buf.is_empty() {
+        return buf.len();
+    }
+    0
+}
+
+#[test]
+fn test_after() {
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    let mut counter = Counter { count: 0 };
+    assert_eq!(counter.incr(3), 3);
+    assert_eq!(counter.get(), 3);
+
+    let mut buf = vec![];
+    fill(&mut buf, 3);
+    assert_eq!(buf, vec![0, 1, 2]);
+    assert_eq!(fill_early_return(&mut buf), 4);
+}
+
+#[test]
+fn test_after_output() {
+    let mut counter = Counter { count: 0 };
+    let mut buf = vec![];
+    trace2::assert_trace!(
+        {
+            counter.incr(2);
+            fill(&mut buf, 2);
+            counter.into_count();
+            consume("ab".to_owned(), 1);
+        },
+        r#"
+        Counter::incr(delta: 2)
+        Counter::incr(self: Counter { count: 2 }) = 2
+        fill(buf: [], n: 2)
+        fill(buf: [0, 1]) = ()
+        Counter::into_count()
+        Counter::into_count = 2
+        consume(name: "ab", n: 1)
+        consume(n: 1) = 3
+        "#
+    );
+}
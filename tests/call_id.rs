@@ -0,0 +1,91 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+use std::panic;
+use std::thread;
+
+#[::trace2::trace2]
+fn outer(n: u32) -> u32 {
+    inner(n) + inner(n + 1)
+}
+
+#[::trace2::trace2]
+fn inner(n: u32) -> u32 {
+    n * 2
+}
+
+#[::trace2::trace2]
+fn explode(n: u32) -> u32 {
+    if n == 0 {
+        panic!("boom");
+    }
+    explode(n - 1)
+}
+
+#[::trace2::trace2]
+fn spawn(n: u32) -> u32 {
+    thread::spawn(move || outer(n)).join().unwrap()
+}
+
+#[test]
+fn test_call_id() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    trace2::set_format(trace2::Format {
+        enter: "#{id} (parent #{parent_id}) {indent}{path}({args})".to_owned(),
+        exit: "#{id} (parent #{parent_id}) {indent}{path}{after} = {ret}".to_owned(),
+        ..trace2::Format::default()
+    });
+
+    assert_eq!(outer(1), 6);
+    assert_eq!(
+        buffer.take(),
+        "#1 (parent #0) >>>> call_id::outer(n: 1)
+#2 (parent #1) >>>>>>>> call_id::inner(n: 1)
+#2 (parent #1) <<<<<<<< call_id::inner = 2
+#3 (parent #1) >>>>>>>> call_id::inner(n: 2)
+#3 (parent #1) <<<<<<<< call_id::inner = 4
+#1 (parent #0) <<<< call_id::outer = 6
+"
+    );
+
+    // Calls that unwind are still removed from the call stack.
+    assert!(panic::catch_unwind(|| explode(3)).is_err());
+    assert_eq!(trace2::depth(), 0);
+    assert_eq!(
+        buffer.take(),
+        "#4 (parent #0) >>>> call_id::explode(n: 3)
+#5 (parent #4) >>>>>>>> call_id::explode(n: 2)
+#6 (parent #5) >>>>>>>>>>>> call_id::explode(n: 1)
+#7 (parent #6) >>>>>>>>>>>>>>>> call_id::explode(n: 0)
+#7 (parent #6) <<<<<<<<<<<<<<<< call_id::explode = <panicked>
+#6 (parent #5) <<<<<<<<<<<< call_id::explode = <panicked>
+#5 (parent #4) <<<<<<<< call_id::explode = <panicked>
+#4 (parent #0) <<<< call_id::explode = <panicked>
+"
+    );
+
+    // Calls in another thread are not children of the spawning call, and lines of the spawning
+    // thread are written when its outermost call returns.
+    assert_eq!(spawn(2), 10);
+    assert_eq!(
+        buffer.take(),
+        "#9 (parent #0) >>>> call_id::outer(n: 2)
+#10 (parent #9) >>>>>>>> call_id::inner(n: 2)
+#10 (parent #9) <<<<<<<< call_id::inner = 4
+#11 (parent #9) >>>>>>>> call_id::inner(n: 3)
+#11 (parent #9) <<<<<<<< call_id::inner = 6
+#9 (parent #0) <<<< call_id::outer = 10
+#8 (parent #0) >>>> call_id::spawn(n: 2)
+#8 (parent #0) <<<< call_id::spawn = 10
+"
+    );
+
+    trace2::set_format(trace2::Format::default());
+}
@@ -0,0 +1,101 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+use std::panic;
+
+use trace2::EventKind;
+
+#[::trace2::trace2]
+fn foo(a: i32, b: i32) -> i32 {
+    sub::bar((a, b)) + baz()
+}
+
+#[::trace2::trace2]
+fn baz() -> i32 {
+    1
+}
+
+#[::trace2::trace2(after(buf))]
+fn fill(buf: &mut Vec<u8>) {
+    buf.push(1);
+}
+
+#[::trace2::trace2]
+fn explode(a: u32) -> u32 {
+    if a > 0 {
+        panic!("boom");
+    }
+    a
+}
+
+mod sub {
+    #[::trace2::trace2]
+    pub fn bar((a, b): (i32, i32)) -> i32 {
+        if a == 1 {
+            2
+        } else {
+            b
+        }
+    }
+}
+
+#[test]
+fn test_capture() {
+    let events = trace2::capture(|| {
+        assert_eq!(foo(1, 2), 3);
+    });
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0].kind, EventKind::Enter);
+    assert_eq!(events[0].path(), "capture::foo");
+    assert_eq!(
+        events[0].args,
+        vec![("a", "1".to_owned()), ("b", "2".to_owned())]
+    );
+    assert_eq!(events[0].ret, None);
+    assert_eq!(events[2].kind, EventKind::Exit);
+    assert_eq!(events[2].path(), "capture::sub::bar");
+    assert_eq!(events[2].depth, 2);
+    assert_eq!(events[2].ret, Some("2".to_owned()));
+    assert_eq!(
+        trace2::render_tree(&events),
+        "foo(a: 1, b: 2)
+  sub::bar(a: 1, b: 2)
+  sub::bar = 2
+  baz()
+  baz = 1
+foo = 3
+"
+    );
+
+    // Depths are relative to the capture, and calls outside it are not captured.
+    let mut inner = vec![];
+    let outer = trace2::capture(|| {
+        foo(0, 5);
+        inner = trace2::capture(|| {
+            let mut buf = vec![];
+            fill(&mut buf);
+        });
+    });
+    assert_eq!(outer.len(), 6);
+    assert_eq!(
+        trace2::render_tree(&inner),
+        "fill(buf: [])
+fill(buf: [1]) = ()
+"
+    );
+
+    let events = trace2::capture(|| {
+        assert!(panic::catch_unwind(|| explode(1)).is_err());
+        explode(0);
+    });
+    assert_eq!(
+        trace2::render_tree(&events),
+        "explode(a: 1)
+explode = <panicked>
+explode(a: 0)
+explode = 0
+"
+    );
+}
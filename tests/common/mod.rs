@@ -0,0 +1,86 @@
+//! Fixtures shared by integration tests.
+
+#![allow(dead_code)]
+
+use std::io::{self, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// A writer appending to a shared buffer.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Take the written text, leaving the buffer empty.
+    pub fn take(&self) -> String {
+        let bytes = self.0.lock().unwrap().split_off(0);
+        String::from_utf8(bytes).unwrap()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A sink that saves records as lines, except records of modules named `quiet`. Clones save
+/// lines to the same place.
+#[derive(Clone)]
+pub struct TestSink {
+    lines: Arc<Mutex<Vec<String>>>,
+    render: fn(&str, &trace2::Record) -> String,
+}
+
+impl TestSink {
+    /// A sink that saves records printed with `{}`.
+    pub fn new() -> Self {
+        TestSink::with_render(|_, record| record.to_string())
+    }
+
+    /// A sink that saves records rendered by `render`, which is called with the event of the
+    /// record, i.e. `enter`, `exit`, `panic` or `elided`.
+    pub fn with_render(render: fn(&str, &trace2::Record) -> String) -> Self {
+        TestSink {
+            lines: Arc::new(Mutex::new(vec![])),
+            render,
+        }
+    }
+
+    /// Take the saved lines.
+    pub fn take(&self) -> Vec<String> {
+        mem::take(&mut *self.lines.lock().unwrap())
+    }
+
+    fn push(&self, event: &str, record: &trace2::Record) {
+        let line = (self.render)(event, record);
+        self.lines.lock().unwrap().push(line);
+    }
+}
+
+impl trace2::Sink for TestSink {
+    fn enabled(&self, module_path: &str) -> bool {
+        !module_path.ends_with("::quiet")
+    }
+
+    fn on_enter(&self, record: &trace2::Record) {
+        self.push("enter", record);
+    }
+
+    fn on_exit(&self, record: &trace2::Record) {
+        self.push("exit", record);
+    }
+
+    fn on_panic(&self, record: &trace2::Record) {
+        self.push("panic", record);
+    }
+
+    fn on_elided(&self, record: &trace2::Record) {
+        self.push("elided", record);
+    }
+}
@@ -0,0 +1,66 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+
+#[::trace2::trace2]
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}
+
+#[::trace2::trace2(after(buf))]
+fn push(buf: &mut Vec<i32>, v: i32) {
+    buf.push(v);
+}
+
+#[::trace2::trace2]
+fn run(n: i32) -> i32 {
+    let mut buf = vec![];
+    push(&mut buf, n);
+    add(add(n, 1), buf[0])
+}
+
+#[test]
+fn test_compact() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    trace2::set_format(trace2::Format {
+        compact: true,
+        ..trace2::Format::default()
+    });
+    assert_eq!(add(1, 2), 3);
+    assert_eq!(run(1), 3);
+    assert_eq!(
+        buffer.take(),
+        ">>>> compact::add(a: 1, b: 2) = 3
+>>>> compact::run(n: 1)
+>>>>>>>> compact::push(buf: [], v: 1)(buf: [1]) = ()
+>>>>>>>> compact::add(a: 1, b: 1) = 2
+>>>>>>>> compact::add(a: 2, b: 1) = 3
+<<<< compact::run = 3
+"
+    );
+
+    trace2::set_format(trace2::Format {
+        compact: true,
+        indent: trace2::Indent::Tree { colors: false },
+        ..trace2::Format::default()
+    });
+    assert_eq!(run(2), 5);
+    assert_eq!(
+        buffer.take(),
+        "compact::run(n: 2)
+├─ compact::push(buf: [], v: 2)(buf: [2]) = ()
+├─ compact::add(a: 2, b: 1) = 3
+└─ compact::add(a: 3, b: 2) = 5
+compact::run = 5
+"
+    );
+
+    trace2::set_format(trace2::Format::default());
+}
@@ -1,10 +1,8 @@
 #![feature(use_extern_macros)]
 #![feature(proc_macro_path_invoc)]
 
-extern crate trace2;
-#[macro_use]
-extern crate log;
 extern crate env_logger;
+extern crate trace2;
 
 struct Func {
     sig: FuncKind,
@@ -85,4 +83,10 @@ fn test_inside_macro() {
         sig: FuncKind::FloorReal,
     };
     func.eval(vec![1, 2, 3]);
+
+    let events = trace2::capture(|| func.eval(vec![4, 5]));
+    assert_eq!(
+        trace2::render_tree(&events),
+        "Func::eval(foo: [4, 5])\nFunc::eval = ()\n"
+    );
 }
@@ -0,0 +1,43 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+use std::panic;
+
+struct Foo;
+
+#[::trace2::trace2]
+impl Foo {
+    fn bar(&self, n: u32) -> u32 {
+        n + 1
+    }
+
+    fn handle(&self, n: u32) -> u32 {
+        (0..n).map(|i| self.bar(i)).sum()
+    }
+}
+
+#[test]
+fn test_expect_calls() {
+    let foo = Foo;
+    assert_eq!(trace2::expect_calls("Foo::bar", 1..=2, || foo.handle(2)), 3);
+    trace2::expect_calls("expect_calls::Foo::handle", 1..=1, || foo.handle(2));
+    trace2::expect_calls("Foo::bar", ..1, || foo.handle(0));
+
+    let result = panic::catch_unwind(|| trace2::expect_calls("Foo::bar", ..=2, || foo.handle(3)));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert_eq!(
+        message,
+        "Expected ..=2 calls of `Foo::bar`, but it is called 3 times:
+Foo::handle(n: 3)
+  Foo::bar(n: 0)
+  Foo::bar = 1
+  Foo::bar(n: 1)
+  Foo::bar = 2
+  Foo::bar(n: 2)
+  Foo::bar = 3
+Foo::handle = 6
+"
+    );
+}
@@ -0,0 +1,47 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+#[derive(Debug)]
+struct Request {
+    id: u64,
+    body: String,
+}
+
+struct Queue {
+    items: Vec<Request>,
+}
+
+#[::trace2::trace2(fields(n = items.len(), first = items.first().map(|r| r.id)))]
+fn sum_ids(items: &[Request]) -> u64 {
+    items.iter().map(|r| r.id).sum()
+}
+
+#[::trace2::trace2(fields(id = req.id, queued = self.items.len()), exit_fields(queued = self.items.len()))]
+impl Queue {
+    fn push(&mut self, req: Request) {
+        self.items.push(req);
+    }
+}
+
+#[test]
+fn test_fields() {
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    let mut queue = Queue { items: vec![] };
+    queue.push(Request {
+        id: 1,
+        body: "hello".to_owned(),
+    });
+    queue.push(Request {
+        id: 2,
+        body: "world".to_owned(),
+    });
+    assert_eq!(sum_ids(&queue.items), 3);
+    assert_eq!(queue.items[1].body, "world");
+}
@@ -0,0 +1,64 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+
+#[::trace2::trace2(fold_recursion)]
+fn fib(n: u32) -> u32 {
+    if n < 2 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+#[::trace2::trace2]
+fn double(n: u32) -> u32 {
+    n * 2
+}
+
+#[::trace2::trace2(fold_recursion)]
+fn walk(n: u32) -> u32 {
+    if n == 0 {
+        double(1)
+    } else {
+        walk(n - 1) + double(n)
+    }
+}
+
+#[test]
+fn test_fold_recursion() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    assert_eq!(fib(4), 3);
+    assert_eq!(fib(1), 1);
+    assert_eq!(
+        buffer.take(),
+        ">>>> fold_recursion::fib(n: 4)
+<<<< fold_recursion::fib = 3 (recursion depth 4, 9 calls)
+>>>> fold_recursion::fib(n: 1)
+<<<< fold_recursion::fib = 1
+"
+    );
+
+    // Calls beneath recursive calls are hidden, but other calls of the outermost call are not.
+    assert_eq!(walk(2), 8);
+    assert_eq!(
+        buffer.take(),
+        ">>>> fold_recursion::walk(n: 2)
+>>>>>>>> fold_recursion::double(n: 2)
+<<<<<<<< fold_recursion::double = 4
+<<<< fold_recursion::walk = 8 (recursion depth 3, 3 calls)
+"
+    );
+
+    // Folded calls are still captured.
+    trace2::expect_calls("fib", 9..=9, || fib(4));
+    trace2::expect_calls("double", 3..=3, || walk(2));
+    assert_eq!(buffer.take(), "");
+}
@@ -0,0 +1,84 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+mod common;
+
+use common::TestSink;
+use std::fmt;
+
+mod sub {
+    #[::trace2::trace2]
+    pub fn foo(a: i32) -> i32 {
+        bar(a) + 1
+    }
+
+    #[::trace2::trace2]
+    fn bar(a: i32) -> i32 {
+        a * 2
+    }
+}
+
+/// A value whose `Debug` output is formatted by a traced function.
+struct Nested;
+
+impl fmt::Debug for Nested {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Nested({})", sub::foo(0))
+    }
+}
+
+#[::trace2::trace2]
+fn show(_v: Nested) {}
+
+#[test]
+fn test_format() {
+    // The default format is filled in by the first record, whose values are formatted by a traced
+    // function here.
+    let sink = TestSink::new();
+    trace2::set_sink(sink.clone());
+    show(Nested);
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>>>>>> format::sub::foo(a: 0)",
+            ">>>>>>>>>>>> format::sub::bar(a: 0)",
+            "<<<<<<<<<<<< format::sub::bar = 0",
+            "<<<<<<<< format::sub::foo = 1",
+            ">>>> format::show(_v: Nested(1))",
+            "<<<< format::show = ()",
+        ]
+    );
+    trace2::set_sink(trace2::LogSink);
+
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    assert_eq!(sub::foo(1), 3);
+
+    trace2::set_format(trace2::Format {
+        indent: trace2::Indent::Depth,
+        path: trace2::PathStyle::CrateRelative,
+        ..trace2::Format::default()
+    });
+    assert_eq!(sub::foo(2), 5);
+
+    trace2::set_format(trace2::Format {
+        enter: "{indent}call {path} with {args}".to_owned(),
+        exit: "{indent}{path} returns {ret}".to_owned(),
+        indent: trace2::Indent::Repeat {
+            enter: "  ".to_owned(),
+            exit: "  ".to_owned(),
+            width: 1,
+        },
+        path: trace2::PathStyle::Omitted,
+        ..trace2::Format::default()
+    });
+    assert_eq!(sub::foo(3), 7);
+
+    trace2::set_format(trace2::Format::default());
+}
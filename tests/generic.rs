@@ -1,10 +1,8 @@
 #![feature(use_extern_macros)]
 #![feature(proc_macro_path_invoc)]
 
-extern crate trace2;
-#[macro_use]
-extern crate log;
 extern crate env_logger;
+extern crate trace2;
 
 #[::trace2::trace2]
 fn boz<T>(arg1: T, arg2: T) -> impl ::std::fmt::Display
@@ -0,0 +1,134 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate log;
+extern crate trace2;
+
+use log::kv::{Error, Key, Value, VisitSource};
+use std::sync::Mutex;
+
+/// Key-values of a record as strings.
+type Pairs = Vec<(String, String)>;
+
+/// A logger that saves messages and key-values of records.
+struct KvLogger {
+    records: Mutex<Vec<(String, Pairs)>>,
+}
+
+impl log::Log for KvLogger {
+    fn enabled(&self, _: &log::Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record) {
+        struct Collector(Pairs);
+
+        impl<'kvs> VisitSource<'kvs> for Collector {
+            fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+                self.0.push((key.to_string(), value.to_string()));
+                Ok(())
+            }
+        }
+
+        let mut collector = Collector(vec![]);
+        record.key_values().visit(&mut collector).unwrap();
+        self.records
+            .lock()
+            .unwrap()
+            .push((record.args().to_string(), collector.0));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: KvLogger = KvLogger {
+    records: Mutex::new(Vec::new()),
+};
+
+struct Foo;
+
+#[::trace2::trace2]
+impl Foo {
+    fn bar(&self, a: u32, name: &str) -> u32 {
+        a + name.len() as u32
+    }
+}
+
+/// Arguments and fields named like fixed keys don't replace them.
+#[::trace2::trace2(fields(event = "load"), after(depth))]
+fn load(id: u32, depth: &mut u32) -> u32 {
+    *depth += 1;
+    id
+}
+
+fn get<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+#[test]
+fn test_kv() {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    assert_eq!(Foo.bar(1, "ab"), 3);
+
+    let records = LOGGER.records.lock().unwrap();
+    assert_eq!(records.len(), 2);
+
+    let (ref message, ref pairs) = records[0];
+    assert_eq!(message, ">>>> kv::Foo::bar(a: 1, name: \"ab\")");
+    assert_eq!(get(pairs, "fn"), Some("Foo::bar"));
+    assert_eq!(get(pairs, "module"), Some("kv"));
+    assert_eq!(get(pairs, "depth"), Some("1"));
+    assert_eq!(get(pairs, "event"), Some("enter"));
+    assert_eq!(get(pairs, "arg.a"), Some("1"));
+    assert_eq!(get(pairs, "arg.name"), Some("\"ab\""));
+    assert_eq!(get(pairs, "ret"), None);
+    assert_eq!(get(pairs, "elapsed"), None);
+
+    let (ref message, ref pairs) = records[1];
+    assert_eq!(message, "<<<< kv::Foo::bar = 3");
+    assert_eq!(get(pairs, "event"), Some("exit"));
+    assert_eq!(get(pairs, "id"), get(&records[0].1, "id"));
+    assert_eq!(get(pairs, "arg.a"), None);
+    assert_eq!(get(pairs, "ret"), Some("3"));
+    assert!(get(pairs, "elapsed").unwrap().parse::<u64>().is_ok());
+    drop(records);
+
+    // Buffered records keep their key-values.
+    trace2::set_format(trace2::Format {
+        indent: trace2::Indent::Tree { colors: false },
+        ..trace2::Format::default()
+    });
+    assert_eq!(Foo.bar(2, ""), 2);
+    trace2::set_format(trace2::Format::default());
+
+    let records = LOGGER.records.lock().unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(get(&records[2].1, "arg.a"), Some("2"));
+    assert_eq!(get(&records[3].1, "ret"), Some("2"));
+    drop(records);
+
+    let mut depth = 5;
+    assert_eq!(load(7, &mut depth), 7);
+    let records = LOGGER.records.lock().unwrap();
+    let (_, ref pairs) = records[4];
+    assert_ne!(get(pairs, "id"), Some("7"));
+    assert_eq!(get(pairs, "arg.id"), Some("7"));
+    assert_eq!(get(pairs, "depth"), Some("1"));
+    assert_eq!(get(pairs, "arg.depth"), Some("5"));
+    assert_eq!(get(pairs, "event"), Some("enter"));
+    assert_eq!(get(pairs, "arg.event"), Some("\"load\""));
+    for (_, pairs) in records[4..].iter() {
+        let mut keys = pairs.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), pairs.len());
+    }
+    let (_, ref pairs) = records[5];
+    assert_eq!(get(pairs, "event"), Some("exit"));
+    assert_eq!(get(pairs, "after.depth"), Some("6"));
+}
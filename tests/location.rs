@@ -0,0 +1,63 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+
+#[::trace2::trace2(location)]
+fn located(a: u32) -> u32 {
+    helper(a) + helper(a + 1)
+}
+
+#[::trace2::trace2(caller)]
+fn helper(a: u32) -> u32 {
+    a * 2
+}
+
+struct Foo;
+
+#[::trace2::trace2(location, caller)]
+impl Foo {
+    fn bar(&self, a: u32) -> u32 {
+        a + 1
+    }
+}
+
+#[test]
+fn test_location() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    assert_eq!(located(1), 6);
+    assert_eq!(Foo.bar(1), 2);
+    assert_eq!(
+        buffer.take(),
+        ">>>> location::located(a: 1) at tests/location.rs:11
+>>>>>>>> location::helper(a: 1) from tests/location.rs:12
+<<<<<<<< location::helper = 2
+>>>>>>>> location::helper(a: 2) from tests/location.rs:12
+<<<<<<<< location::helper = 4
+<<<< location::located = 6
+>>>> location::Foo::bar(a: 1) at tests/location.rs:24 from tests/location.rs:35
+<<<< location::Foo::bar = 2
+"
+    );
+
+    trace2::set_format(trace2::Format {
+        compact: true,
+        ..trace2::Format::default()
+    });
+    assert_eq!(located(1), 6);
+    assert_eq!(
+        buffer.take(),
+        ">>>> location::located(a: 1) at tests/location.rs:11
+>>>>>>>> location::helper(a: 1) = 2 from tests/location.rs:12
+>>>>>>>> location::helper(a: 2) = 4 from tests/location.rs:12
+<<<< location::located = 6
+"
+    );
+    trace2::set_format(trace2::Format::default());
+}
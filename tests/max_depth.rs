@@ -0,0 +1,81 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::TestSink;
+
+#[::trace2::trace2]
+fn fib(n: u32) -> u32 {
+    if n < 2 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+#[::trace2::trace2(max_depth = 1)]
+fn outer(n: u32) -> u32 {
+    fib(n)
+}
+
+#[test]
+fn test_max_depth() {
+    // Summaries have the identity of the call below which calls are elided.
+    let sink = TestSink::with_render(|event, record| match event {
+        "elided" => format!("{} in {}#{}", record, record.fn_name(), record.id()),
+        _ => record.to_string(),
+    });
+    trace2::set_sink(sink.clone());
+
+    trace2::set_max_depth(Some(2));
+    assert_eq!(fib(4), 3);
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>> max_depth::fib(n: 4)",
+            ">>>>>>>> max_depth::fib(n: 3)",
+            ">>>>>>>>>>>> ... 4 calls elided below depth 2 in fib#2",
+            "<<<<<<<< max_depth::fib = 2",
+            ">>>>>>>> max_depth::fib(n: 2)",
+            ">>>>>>>>>>>> ... 2 calls elided below depth 2 in fib#7",
+            "<<<<<<<< max_depth::fib = 1",
+            "<<<< max_depth::fib = 3",
+        ]
+    );
+
+    trace2::set_max_depth(None);
+    assert_eq!(outer(4), 3);
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>> max_depth::outer(n: 4)",
+            ">>>>>>>> max_depth::fib(n: 4)",
+            ">>>>>>>>>>>> ... 8 calls elided below depth 2 in fib#11",
+            "<<<<<<<< max_depth::fib = 3",
+            "<<<< max_depth::outer = 3",
+        ]
+    );
+
+    // Calls are not elided after the function with `max_depth` returns.
+    assert_eq!(fib(1), 1);
+    assert_eq!(
+        sink.take(),
+        vec![">>>> max_depth::fib(n: 1)", "<<<< max_depth::fib = 1"]
+    );
+
+    // No records are emitted with a maximum depth of 0.
+    trace2::set_max_depth(Some(0));
+    assert_eq!(outer(4), 3);
+    trace2::set_max_depth(None);
+    assert!(sink.take().is_empty());
+
+    // Captured calls are not elided.
+    trace2::set_max_depth(Some(1));
+    trace2::expect_calls("fib", 5..=5, || fib(3));
+    trace2::expect_calls("fib", 9..=9, || outer(4));
+    trace2::set_max_depth(None);
+    assert!(sink.take().is_empty());
+}
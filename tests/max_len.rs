@@ -0,0 +1,50 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+#[::trace2::trace2]
+fn checksum(data: Vec<u8>) -> Vec<u8> {
+    data.iter().map(|b| b.wrapping_add(1)).collect()
+}
+
+#[::trace2::trace2(max_len = 8)]
+fn short(data: &[u8], name: &str) -> usize {
+    data.len() + name.len()
+}
+
+#[test]
+fn test_max_len() {
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    assert_eq!(trace2::max_len(), None);
+    assert_eq!(checksum(vec![0; 4]), vec![1; 4]);
+
+    trace2::set_max_len(Some(16));
+    assert_eq!(trace2::max_len(), Some(16));
+    assert_eq!(checksum(vec![0; 4096]).len(), 4096);
+    assert_eq!(short(&[0; 4096], "a very long name"), 4112);
+
+    // `0` truncates every value, like the attribute option.
+    trace2::set_max_len(Some(0));
+    assert_eq!(trace2::max_len(), Some(0));
+
+    trace2::set_max_len(None);
+    assert_eq!(trace2::max_len(), None);
+    assert_eq!(short(&[0; 2], "ab"), 4);
+}
+
+#[test]
+fn test_max_len_output() {
+    trace2::assert_trace!(
+        short(&[0; 4096], "a very long name"),
+        r#"
+        short(data: [0, 0, 0…(+12280 bytes), name: "a very …(+10 bytes))
+        short = 4112
+        "#
+    );
+}
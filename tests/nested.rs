@@ -1,10 +1,8 @@
 #![feature(proc_macro_mod)]
 #![feature(proc_macro_path_invoc)]
 
-extern crate trace2;
-#[macro_use]
-extern crate log;
 extern crate env_logger;
+extern crate trace2;
 
 mod implementation {
     #![::trace2::trace2]
@@ -59,4 +57,18 @@ fn test_nested() {
         .init();
 
     implementation::foo(1, 2);
+
+    let events = trace2::capture(|| implementation::foo(1, 2));
+    assert_eq!(
+        trace2::render_tree(&events),
+        "implementation::foo(a: 1, b: 2)
+  implementation::sub::bar(a: 1, b: 2)
+  implementation::sub::bar = 2
+  implementation::nyan()
+    implementation::Dog::bite()
+    implementation::Dog::bite = 12
+  implementation::nyan = 7
+implementation::foo = ()
+"
+    );
 }
@@ -0,0 +1,95 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+use std::env;
+use std::fs;
+use std::thread;
+
+use trace2::FlightRecorder;
+
+#[::trace2::trace2]
+fn work(n: u32, data: &[u8]) -> u32 {
+    if n == 0 {
+        return 0;
+    }
+    work(n - 1, data) + 1
+}
+
+#[::trace2::trace2]
+fn explode(a: u32) -> u32 {
+    if a > 0 {
+        panic!("boom");
+    }
+    a
+}
+
+fn dumped_lines(path: &::std::path::Path) -> Vec<String> {
+    let lines = fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.to_owned())
+        .collect();
+    let _ = fs::remove_file(path);
+    lines
+}
+
+#[test]
+fn test_recorder() {
+    let path = env::temp_dir().join(format!("trace2-recorder-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    trace2::init_flight_recorder(FlightRecorder::new(4).max_len(8).dump_to_file(&path));
+    let current = thread::current();
+    let name = current.name().unwrap();
+
+    work(3, &[1; 10]);
+    trace2::dump_recent();
+    assert_eq!(
+        dumped_lines(&path),
+        vec![
+            format!("[{}] <<<<<<<<<<<<<<<< recorder::work = 0", name),
+            format!("[{}] <<<<<<<<<<<< recorder::work = 1", name),
+            format!("[{}] <<<<<<<< recorder::work = 2", name),
+            format!("[{}] <<<< recorder::work = 3", name),
+        ]
+    );
+    trace2::dump_recent();
+    assert!(dumped_lines(&path).is_empty());
+
+    // Values are truncated, and records are dumped when a thread panics.
+    let handle = thread::Builder::new()
+        .name("worker".to_owned())
+        .spawn(|| {
+            work(0, &[1; 10]);
+            explode(1);
+        })
+        .unwrap();
+    assert!(handle.join().is_err());
+    assert_eq!(
+        dumped_lines(&path),
+        vec![
+            "[worker] >>>> recorder::work(n: 0, data: [1, 1, 1…(+22 bytes))",
+            "[worker] <<<< recorder::work = 0",
+            "[worker] >>>> recorder::explode(a: 1)",
+        ]
+    );
+
+    // In per-thread mode, each thread keeps its own records.
+    trace2::init_flight_recorder(FlightRecorder::new(2).per_thread().dump_to_file(&path));
+    work(1, &[]);
+    thread::Builder::new()
+        .name("other".to_owned())
+        .spawn(|| work(0, &[]))
+        .unwrap()
+        .join()
+        .unwrap();
+    trace2::dump_recent();
+    assert_eq!(
+        dumped_lines(&path),
+        vec![
+            format!("[{}] <<<<<<<< recorder::work = 0", name),
+            format!("[{}] <<<< recorder::work = 1", name),
+        ]
+    );
+}
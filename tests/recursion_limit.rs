@@ -0,0 +1,77 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+use std::panic;
+use trace2::EventKind;
+
+#[::trace2::trace2(recursion_limit = 5)]
+fn nest(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        nest(n - 1) + 1
+    }
+}
+
+#[test]
+fn test_recursion_limit() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+    assert_eq!(nest(3), 3);
+    buffer.take();
+
+    let report = "Recursion limit 5 of `recursion_limit::nest` is reached. Latest calls:\n  \
+                  #1 recursion_limit::nest(n: 10)\n  #2 recursion_limit::nest(n: 9)\n  \
+                  #3 recursion_limit::nest(n: 8)\n  #4 recursion_limit::nest(n: 7)\n  \
+                  #5 recursion_limit::nest(n: 6)";
+    let payload = panic::catch_unwind(|| nest(10)).unwrap_err();
+    assert_eq!(payload.downcast_ref::<String>().unwrap(), report);
+    assert!(buffer.take().contains(&format!("\n{}\n", report)));
+    // Calls are not counted anymore after unwinding.
+    assert_eq!(nest(3), 3);
+    buffer.take();
+
+    // The report is written as a warning before the call goes on.
+    trace2::set_recursion_limit_panics(false);
+    assert_eq!(nest(5), 5);
+    let report = "Recursion limit 5 of `recursion_limit::nest` is reached. Latest calls:\n  \
+                  #1 recursion_limit::nest(n: 5)\n  #2 recursion_limit::nest(n: 4)\n  \
+                  #3 recursion_limit::nest(n: 3)\n  #4 recursion_limit::nest(n: 2)\n  \
+                  #5 recursion_limit::nest(n: 1)";
+    assert_eq!(
+        buffer.take(),
+        format!(
+            "\
+>>>> recursion_limit::nest(n: 5)
+>>>>>>>> recursion_limit::nest(n: 4)
+>>>>>>>>>>>> recursion_limit::nest(n: 3)
+>>>>>>>>>>>>>>>> recursion_limit::nest(n: 2)
+>>>>>>>>>>>>>>>>>>>> recursion_limit::nest(n: 1)
+{}
+>>>>>>>>>>>>>>>>>>>>>>>> recursion_limit::nest(n: 0)
+<<<<<<<<<<<<<<<<<<<<<<<< recursion_limit::nest = 0
+<<<<<<<<<<<<<<<<<<<< recursion_limit::nest = 1
+<<<<<<<<<<<<<<<< recursion_limit::nest = 2
+<<<<<<<<<<<< recursion_limit::nest = 3
+<<<<<<<< recursion_limit::nest = 4
+<<<< recursion_limit::nest = 5
+",
+            report
+        )
+    );
+
+    let events = trace2::capture(|| assert_eq!(nest(5), 5));
+    let event = events
+        .iter()
+        .find(|event| event.kind == EventKind::RecursionLimit)
+        .unwrap();
+    assert_eq!(event.depth, 5);
+    assert_eq!(event.args, vec![("n", "1".to_owned())]);
+    assert_eq!(event.report.as_ref().unwrap(), report);
+    trace2::set_recursion_limit_panics(true);
+}
@@ -0,0 +1,108 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+#[derive(Debug)]
+struct Login {
+    user: String,
+    password: String,
+}
+
+#[::trace2::trace2(redact(pin))]
+fn check_pin(user: &str, pin: u32) -> bool {
+    user.len() as u32 == pin
+}
+
+#[::trace2::trace2]
+fn check_login(
+    Login {
+        user,
+        password: pass,
+    }: Login,
+    api_key: &str,
+) -> bool {
+    user == pass && !api_key.is_empty()
+}
+
+#[::trace2::trace2(redact_patterns())]
+fn unsafe_login(password: &str) -> usize {
+    password.len()
+}
+
+#[::trace2::trace2]
+fn feed(monkey: &str, key: u32) -> usize {
+    monkey.len() + key as usize
+}
+
+#[::trace2::trace2(fields(secret_len = secret.len()), after(secret))]
+fn rotate(secret: &mut String, salt: &str) {
+    secret.push_str(salt);
+}
+
+#[test]
+fn test_redact() {
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    assert!(check_pin("abcd", 4));
+    let login = Login {
+        user: "alice".to_owned(),
+        password: "alice".to_owned(),
+    };
+    assert!(check_login(login, "123"));
+    assert_eq!(unsafe_login("hunter2"), 7);
+
+    let mut secret = "foo".to_owned();
+    rotate(&mut secret, "bar");
+    assert_eq!(secret, "foobar");
+    assert_eq!(feed("banana", 2), 8);
+}
+
+#[test]
+fn test_redact_output() {
+    let login = Login {
+        user: "alice".to_owned(),
+        password: "hunter2".to_owned(),
+    };
+    let events = trace2::capture(|| {
+        check_pin("abcd", 1234);
+        check_login(login, "s3cr3t-api");
+        feed("banana", 2);
+    });
+    let output = trace2::render_tree(&events);
+    assert!(output.contains("***"));
+    for raw in &["1234", "hunter2", "s3cr3t-api"] {
+        assert!(!output.contains(raw), "{} is printed in {}", raw, output);
+    }
+
+    let mut secret = "foo".to_owned();
+    trace2::assert_trace!(
+        {
+            unsafe_login("hunter2");
+            rotate(&mut secret, "bar");
+            feed("banana", 2);
+        },
+        r#"
+        unsafe_login(password: "hunter2")
+        unsafe_login = 7
+        rotate(secret: ***, salt: "bar", secret_len: ***)
+        rotate(secret: ***) = ()
+        feed(monkey: "banana", key: 2)
+        feed = 8
+        "#
+    );
+
+    trace2::set_redact_patterns(&["*user*"]);
+    trace2::assert_trace!(
+        check_pin("abcd", 4),
+        r#"
+        check_pin(user: ***, pin: ***)
+        check_pin = true
+        "#
+    );
+    trace2::set_redact_patterns(&[]);
+}
@@ -0,0 +1,101 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::TestSink;
+use std::panic;
+
+#[::trace2::trace2(root, on = "err")]
+fn handle(req: u32) -> Result<u32, String> {
+    let v = parse(req);
+    if v > 10 {
+        Err(format!("too large: {}", v))
+    } else {
+        Ok(v)
+    }
+}
+
+#[::trace2::trace2]
+fn parse(req: u32) -> u32 {
+    if req == 0 {
+        panic!("empty request");
+    }
+    req * 2
+}
+
+#[::trace2::trace2(root, on = "panic")]
+fn batch(reqs: &[u32]) -> Vec<Result<u32, String>> {
+    reqs.iter().map(|&req| handle(req)).collect()
+}
+
+#[::trace2::trace2]
+fn serve(req: u32) -> Result<u32, String> {
+    handle(req)
+}
+
+#[test]
+fn test_root() {
+    let sink = TestSink::new();
+    trace2::set_sink(sink.clone());
+
+    assert_eq!(handle(1), Ok(2));
+    assert!(sink.take().is_empty());
+    assert_eq!(serve(6), Err("too large: 12".to_owned()));
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>> root::serve(req: 6)",
+            ">>>>>>>> root::handle(req: 6)",
+            ">>>>>>>>>>>> root::parse(req: 6)",
+            "<<<<<<<<<<<< root::parse = 12",
+            "<<<<<<<< root::handle = Err(\"too large: 12\")",
+            "<<<< root::serve = Err(\"too large: 12\")",
+        ]
+    );
+    assert!(panic::catch_unwind(|| handle(0)).is_err());
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>> root::handle(req: 0)",
+            ">>>>>>>> root::parse(req: 0)",
+            "<<<<<<<< root::parse = <panicked>",
+            "<<<< root::handle = <panicked>",
+        ]
+    );
+
+    // Failed root calls within another root call are emitted only if the outer one fails.
+    assert_eq!(batch(&[1, 6]).len(), 2);
+    assert!(sink.take().is_empty());
+    assert!(panic::catch_unwind(|| batch(&[6, 0])).is_err());
+    assert_eq!(
+        sink.take(),
+        vec![
+            ">>>> root::batch(reqs: [6, 0])",
+            ">>>>>>>> root::handle(req: 6)",
+            ">>>>>>>>>>>> root::parse(req: 6)",
+            "<<<<<<<<<<<< root::parse = 12",
+            "<<<<<<<< root::handle = Err(\"too large: 12\")",
+            ">>>>>>>> root::handle(req: 0)",
+            ">>>>>>>>>>>> root::parse(req: 0)",
+            "<<<<<<<<<<<< root::parse = <panicked>",
+            "<<<<<<<< root::handle = <panicked>",
+            "<<<< root::batch = <panicked>",
+        ]
+    );
+
+    // Records are not buffered for testing helpers, which see every call.
+    trace2::assert_trace!(
+        handle(1),
+        r#"
+        handle(req: 1)
+          parse(req: 1)
+          parse = 2
+        handle = Ok(2)
+        "#
+    );
+    trace2::expect_calls("parse", 2..=2, || batch(&[1, 2]));
+    assert!(sink.take().is_empty());
+}
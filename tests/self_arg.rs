@@ -0,0 +1,66 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate env_logger;
+extern crate trace2;
+
+use std::rc::Rc;
+
+#[derive(Debug)]
+enum State {
+    Idle,
+    Running,
+}
+
+#[derive(Debug)]
+struct Machine {
+    id: u32,
+    state: State,
+    payload: Vec<u8>,
+}
+
+#[::trace2::trace2(self)]
+impl Machine {
+    fn new(id: u32) -> Machine {
+        Machine {
+            id,
+            state: State::Idle,
+            payload: vec![],
+        }
+    }
+
+    fn start(&mut self) {
+        self.state = State::Running;
+    }
+
+    fn into_id(self: Box<Self>) -> u32 {
+        self.id
+    }
+
+    fn rc_id(self: Rc<Self>, offset: u32) -> u32 {
+        self.id + offset
+    }
+}
+
+impl Machine {
+    #[::trace2::trace2(self_fields(state, id))]
+    fn stop(&mut self, reason: &str) -> usize {
+        self.state = State::Idle;
+        reason.len()
+    }
+}
+
+#[test]
+fn test_self_arg() {
+    env_logger::Builder::from_default_env()
+        .default_format_timestamp(false)
+        .default_format_module_path(false)
+        .init();
+
+    let mut machine = Machine::new(7);
+    machine.start();
+    assert_eq!(machine.stop("done"), 4);
+    assert!(machine.payload.is_empty());
+    assert_eq!(Box::new(Machine::new(3)).into_id(), 3);
+    assert_eq!(Rc::new(Machine::new(5)).rc_id(1), 6);
+}
@@ -0,0 +1,73 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::TestSink;
+use std::panic;
+
+/// Render a record with its structured fields, for records of enabled modules.
+fn render(event: &str, record: &trace2::Record) -> String {
+    match event {
+        "enter" => {
+            let args = record
+                .args()
+                .iter()
+                .map(|&(name, value)| format!("{}={:?}", name, value))
+                .collect::<Vec<_>>();
+            format!(
+                "enter {} {} depth={} {}",
+                record.module_path(),
+                record.fn_name(),
+                record.depth(),
+                args.join(",")
+            )
+        }
+        "exit" => {
+            assert!(record.elapsed().is_some());
+            format!("exit {} ret={:?}", record.fn_name(), record.ret().unwrap())
+        }
+        _ => format!("{} {}", event, record.fn_name()),
+    }
+}
+
+#[::trace2::trace2]
+fn outer(a: u32) -> u32 {
+    quiet::inner(a) + 1
+}
+
+#[::trace2::trace2]
+fn explode(a: u32) -> u32 {
+    if a > 0 {
+        panic!("boom");
+    }
+    a
+}
+
+mod quiet {
+    #[::trace2::trace2]
+    pub fn inner(a: u32) -> u32 {
+        a * 2
+    }
+}
+
+#[test]
+fn test_sink() {
+    let sink = TestSink::with_render(render);
+    trace2::set_sink(sink.clone());
+
+    assert_eq!(outer(2), 5);
+    assert!(panic::catch_unwind(|| explode(1)).is_err());
+
+    assert_eq!(
+        sink.take(),
+        vec![
+            "enter sink outer depth=1 a=2",
+            "exit outer ret=5",
+            "enter sink explode depth=1 a=1",
+            "panic explode",
+        ]
+    );
+}
@@ -0,0 +1,94 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+use std::env;
+use std::fs;
+use std::panic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trace2::assert_trace;
+
+#[::trace2::trace2]
+fn foo(a: i32, b: i32) -> i32 {
+    bar((a, b))
+}
+
+#[::trace2::trace2]
+fn bar((a, b): (i32, i32)) -> i32 {
+    if a == 1 {
+        2
+    } else {
+        b
+    }
+}
+
+#[::trace2::trace2]
+fn timed(start: Instant, id: thread::ThreadId, ptr: *const u8) -> Duration {
+    start.elapsed()
+}
+
+#[test]
+fn test_snapshot() {
+    assert_trace!(
+        foo(1, 2),
+        r#"
+        foo(a: 1, b: 2)
+          bar(a: 1, b: 2)
+          bar = 2
+        foo = 2
+    "#
+    );
+    assert_trace!(foo(0, 5), file = "tests/snapshots/foo.trace");
+
+    let x = 7u8;
+    assert_trace!(
+        timed(Instant::now(), thread::current().id(), &x),
+        r#"
+            timed(start: Instant { tv_sec: <timestamp>, tv_nsec: <timestamp> }, id: ThreadId(<thread>), ptr: <ptr>)
+            timed = <duration>
+        "#
+    );
+
+    // Mismatches fail with both trees.
+    let result = panic::catch_unwind(|| assert_trace!(foo(1, 2), "foo(a: 1, b: 2)\nfoo = 3"));
+    let message = *result.unwrap_err().downcast::<String>().unwrap();
+    assert!(message.contains("expected:\nfoo(a: 1, b: 2)\nfoo = 3\n"));
+    assert!(message.contains("actual:\nfoo(a: 1, b: 2)\n  bar(a: 1, b: 2)\n  bar = 2\nfoo = 2\n"));
+    assert!(
+        panic::catch_unwind(|| assert_trace!(foo(1, 2), file = "tests/snapshots/none")).is_err()
+    );
+
+    // Snapshots are written in update mode.
+    let dir = env::temp_dir().join(format!("trace2-snapshot-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let dir_str = dir.to_str().unwrap();
+    fs::write(
+        dir.join("test.rs"),
+        "fn test() {\n    assert_trace!(foo(1, 2), \"\");\n    assert_trace!(foo(1, 2), \"\");\n}\n",
+    ).unwrap();
+    env::set_var("TRACE2_UPDATE_SNAPSHOTS", "1");
+    let events = trace2::capture(|| {
+        foo(1, 2);
+    });
+    trace2::__assert_trace_inline(&events, "", dir_str, "test.rs", 2);
+    trace2::__assert_trace_inline(&events, "", dir_str, "test.rs", 3);
+    trace2::__assert_trace_file(&events, dir_str, "snapshots/foo.trace");
+    env::remove_var("TRACE2_UPDATE_SNAPSHOTS");
+    let tree = "foo(a: 1, b: 2)\n  bar(a: 1, b: 2)\n  bar = 2\nfoo = 2\n";
+    assert_eq!(
+        fs::read_to_string(dir.join("test.rs")).unwrap(),
+        format!(
+            "fn test() {{\n    assert_trace!(foo(1, 2), r#\"\n{0}\"#);\n    \
+             assert_trace!(foo(1, 2), r#\"\n{0}\"#);\n}}\n",
+            tree
+        )
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("snapshots/foo.trace")).unwrap(),
+        tree
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,88 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+use std::env;
+use std::process::Command;
+
+#[::trace2::trace2]
+fn foo(a: i32, b: i32) -> i32 {
+    bar((a, b))
+}
+
+#[::trace2::trace2]
+fn bar((a, b): (i32, i32)) -> i32 {
+    if a == 1 {
+        panic!("boom");
+    }
+    b
+}
+
+#[::trace2::test]
+fn test_pass() {
+    assert_eq!(foo(0, 2), 2);
+}
+
+#[::trace2::test]
+fn test_result() -> Result<(), String> {
+    if foo(0, 3) == 3 {
+        Ok(())
+    } else {
+        Err("unexpected".to_owned())
+    }
+}
+
+#[::trace2::test]
+#[should_panic(expected = "boom")]
+fn test_panic() {
+    foo(0, 2);
+    foo(1, 2);
+}
+
+/// Whether failing tests are run, by `test_failure_output` in a child process.
+fn run_failing() -> bool {
+    env::var_os("TRACE2_RUN_FAILING").is_some()
+}
+
+#[::trace2::test]
+fn failing_result() -> Result<(), String> {
+    if run_failing() {
+        return Err(format!("got {}", foo(0, 3)));
+    }
+    Ok(())
+}
+
+#[::trace2::test]
+fn failing_panic() {
+    if run_failing() {
+        foo(1, 2);
+    }
+}
+
+#[test]
+fn test_failure_output() {
+    let expected = [
+        (
+            "failing_result",
+            "Trace of the failed test:\n\
+             foo(a: 0, b: 3)\n  bar(a: 0, b: 3)\n  bar = 3\nfoo = 3\n",
+        ),
+        (
+            "failing_panic",
+            "Trace of the failed test:\n\
+             foo(a: 1, b: 2)\n  bar(a: 1, b: 2)\n  bar = <panicked>\nfoo = <panicked>\n",
+        ),
+    ];
+    // The trace is printed when a test fails by returning `Err` or by panicking.
+    for &(name, trace) in expected.iter() {
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", name, "--nocapture", "--test-threads=1"])
+            .env("TRACE2_RUN_FAILING", "1")
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(trace), "{}", stderr);
+    }
+}
@@ -0,0 +1,95 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+use std::thread;
+
+#[::trace2::trace2]
+fn work(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        work(n - 1) + n
+    }
+}
+
+/// Expected lines of `work(n)` called in a thread named `thread`.
+fn expected_lines(thread: &str, n: u32) -> Vec<String> {
+    let mut lines = vec![];
+    for depth in 0..=n {
+        lines.push(format!(
+            "[{}#{}] {} thread::work(n: {})",
+            thread,
+            depth + 1,
+            ">>>>".repeat(depth as usize + 1),
+            n - depth
+        ));
+    }
+    for depth in (0..=n).rev() {
+        let m = n - depth;
+        lines.push(format!(
+            "[{}#{}] {} thread::work = {}",
+            thread,
+            2 * (n + 1) - depth,
+            "<<<<".repeat(depth as usize + 1),
+            m * (m + 1) / 2
+        ));
+    }
+    lines
+}
+
+#[test]
+fn test_thread() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    trace2::set_format(trace2::Format {
+        enter: "[{thread}#{seq}] {indent}{path}({args})".to_owned(),
+        exit: "[{thread}#{seq}] {indent}{path}{after} = {ret}".to_owned(),
+        ..trace2::Format::default()
+    });
+
+    let handles = (0..3)
+        .map(|i| {
+            thread::Builder::new()
+                .name(format!("worker-{}", i))
+                .spawn(move || work(i))
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let unnamed = thread::spawn(|| (work(1), format!("{:?}", thread::current().id())));
+    for (i, handle) in handles.into_iter().enumerate() {
+        let i = i as u32;
+        assert_eq!(handle.join().unwrap(), i * (i + 1) / 2);
+    }
+    let (ret, unnamed_id) = unnamed.join().unwrap();
+    assert_eq!(ret, 1);
+
+    // Lines of each thread are written together, with sequence numbers counted per thread.
+    let output = buffer.take();
+    let lines = output.lines().collect::<Vec<_>>();
+    let unnamed_id = unnamed_id
+        .trim_start_matches("ThreadId(")
+        .trim_end_matches(')');
+    let threads = (0..3u32)
+        .map(|i| (format!("worker-{}", i), i))
+        .chain(Some((unnamed_id.to_owned(), 1)));
+    let mut count = 0;
+    for (thread, n) in threads {
+        let expected = expected_lines(&thread, n);
+        let prefix = format!("[{}#", thread);
+        let start = lines
+            .iter()
+            .position(|line| line.starts_with(&prefix))
+            .unwrap();
+        assert_eq!(lines[start..start + expected.len()].to_vec(), expected);
+        count += expected.len();
+    }
+    assert_eq!(lines.len(), count);
+
+    trace2::set_format(trace2::Format::default());
+}
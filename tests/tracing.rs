@@ -0,0 +1,211 @@
+#![cfg(feature = "tracing")]
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+extern crate tracing;
+
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// A subscriber that saves what happens to spans and events as strings, except spans and events
+/// of modules named `quiet`. Spans are named by their targets and names.
+#[derive(Clone, Default)]
+struct TestSubscriber {
+    next_id: Arc<AtomicUsize>,
+    names: Arc<Mutex<Vec<String>>>,
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+struct FieldsVisitor(Vec<String>);
+
+impl Visit for FieldsVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push(format!("{}: {}", field.name(), value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.push(format!("{}: {:?}", field.name(), value));
+    }
+}
+
+impl TestSubscriber {
+    fn name(&self, id: &Id) -> String {
+        self.names.lock().unwrap()[id.into_u64() as usize - 1].clone()
+    }
+}
+
+impl Subscriber for TestSubscriber {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        !metadata.target().ends_with("::quiet")
+    }
+
+    fn new_span(&self, span: &Attributes) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) as u64 + 1;
+        let mut visitor = FieldsVisitor(vec![]);
+        span.record(&mut visitor);
+        let metadata = span.metadata();
+        let name = format!("{}::{}", metadata.target(), metadata.name());
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("new {}({})", name, visitor.0.join(", ")));
+        self.names.lock().unwrap().push(name);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record) {
+        let mut visitor = FieldsVisitor(vec![]);
+        values.record(&mut visitor);
+        let name = self.name(span);
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("record {}({})", name, visitor.0.join(", ")));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event) {
+        let mut visitor = FieldsVisitor(vec![]);
+        event.record(&mut visitor);
+        self.events.lock().unwrap().push(format!(
+            "event {}({})",
+            event.metadata().target(),
+            visitor.0.join(", ")
+        ));
+    }
+
+    fn enter(&self, span: &Id) {
+        let name = self.name(span);
+        self.events.lock().unwrap().push(format!("enter {}", name));
+    }
+
+    fn exit(&self, span: &Id) {
+        let name = self.name(span);
+        self.events.lock().unwrap().push(format!("exit {}", name));
+    }
+}
+
+#[::trace2::trace2]
+fn outer(a: u32, password: &str) -> u32 {
+    inner(a) + quiet::hidden(password)
+}
+
+mod quiet {
+    #[::trace2::trace2]
+    pub fn hidden(s: &str) -> u32 {
+        s.len() as u32
+    }
+}
+
+#[::trace2::trace2(after(buf))]
+fn inner(buf: u32) -> u32 {
+    buf * 2
+}
+
+#[::trace2::trace2(location, caller)]
+fn located(a: u32) -> u32 {
+    a
+}
+
+#[::trace2::trace2]
+fn nest(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        nest(n - 1) + 1
+    }
+}
+
+#[::trace2::trace2(max_depth = 1)]
+fn shallow(n: u32) -> u32 {
+    nest(n)
+}
+
+#[::trace2::trace2(recursion_limit = 2)]
+fn limited(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        limited(n - 1) + 1
+    }
+}
+
+#[test]
+fn test_tracing() {
+    trace2::set_sink(trace2::TracingSink);
+    let subscriber = TestSubscriber::default();
+    tracing::subscriber::with_default(subscriber.clone(), || {
+        assert_eq!(outer(1, "abc"), 5);
+    });
+    // Spans are filtered by the module of the function, so `quiet::hidden` has none.
+    assert_eq!(
+        subscriber.events.lock().unwrap().split_off(0),
+        vec![
+            "new tracing::outer(fn: outer, module: tracing, id: 1, parent_id: 0)",
+            "record tracing::outer(arg.a: 1)",
+            "record tracing::outer(arg.password: ***)",
+            "enter tracing::outer",
+            "new tracing::inner(fn: inner, module: tracing, id: 2, parent_id: 1)",
+            "record tracing::inner(arg.buf: 1)",
+            "enter tracing::inner",
+            "record tracing::inner(after: buf: 1)",
+            "record tracing::inner(ret: 2)",
+            "exit tracing::inner",
+            "record tracing::outer(ret: 5)",
+            "exit tracing::outer",
+        ]
+    );
+
+    // Options are applied by the runtime before records are passed to the sink.
+    tracing::subscriber::with_default(subscriber.clone(), || {
+        assert_eq!(located(1), 1);
+        assert_eq!(shallow(3), 3);
+    });
+    assert_eq!(
+        subscriber.events.lock().unwrap().split_off(0),
+        vec![
+            "new tracing::located(fn: located, module: tracing, id: 4, parent_id: 0)",
+            "record tracing::located(arg.a: 1)",
+            "record tracing::located(location: tests/tracing.rs:112)",
+            "record tracing::located(caller: tests/tracing.rs:167:20)",
+            "enter tracing::located",
+            "record tracing::located(ret: 1)",
+            "exit tracing::located",
+            "new tracing::shallow(fn: shallow, module: tracing, id: 5, parent_id: 0)",
+            "record tracing::shallow(arg.n: 3)",
+            "enter tracing::shallow",
+            "new tracing::nest(fn: nest, module: tracing, id: 6, parent_id: 5)",
+            "record tracing::nest(arg.n: 3)",
+            "enter tracing::nest",
+            "event tracing(message: calls elided below the maximum depth, calls: 3, depth: 2)",
+            "record tracing::nest(ret: 3)",
+            "exit tracing::nest",
+            "record tracing::shallow(ret: 3)",
+            "exit tracing::shallow",
+        ]
+    );
+
+    // Reached recursion limits are reported by events.
+    trace2::set_recursion_limit_panics(false);
+    tracing::subscriber::with_default(subscriber.clone(), || {
+        assert_eq!(limited(2), 2);
+    });
+    trace2::set_recursion_limit_panics(true);
+    let events = subscriber.events.lock().unwrap().split_off(0);
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| event.starts_with("event"))
+            .collect::<Vec<_>>(),
+        vec![
+            "event tracing(message: Recursion limit 2 of `tracing::limited` is reached. Latest calls:\n  \
+             #1 tracing::limited(n: 2)\n  #2 tracing::limited(n: 1))"
+        ]
+    );
+}
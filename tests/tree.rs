@@ -0,0 +1,75 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+
+#[::trace2::trace2]
+fn fib(n: u32) -> u32 {
+    if n < 2 {
+        n
+    } else {
+        fib(n - 1) + fib(n - 2)
+    }
+}
+
+#[::trace2::trace2]
+fn run(n: u32) -> u32 {
+    leaf();
+    fib(n)
+}
+
+#[::trace2::trace2]
+fn leaf() {}
+
+#[test]
+fn test_tree() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    trace2::set_format(trace2::Format {
+        indent: trace2::Indent::Tree { colors: false },
+        ..trace2::Format::default()
+    });
+    assert_eq!(run(3), 2);
+    assert_eq!(
+        buffer.take(),
+        "tree::run(n: 3)
+├─ tree::leaf()
+│  tree::leaf = ()
+└─ tree::fib(n: 3)
+   ├─ tree::fib(n: 2)
+   │  ├─ tree::fib(n: 1)
+   │  │  tree::fib = 1
+   │  └─ tree::fib(n: 0)
+   │     tree::fib = 0
+   │  tree::fib = 1
+   └─ tree::fib(n: 1)
+      tree::fib = 1
+   tree::fib = 2
+tree::run = 2
+"
+    );
+
+    trace2::set_format(trace2::Format {
+        indent: trace2::Indent::Tree { colors: true },
+        path: trace2::PathStyle::Omitted,
+        ..trace2::Format::default()
+    });
+    assert_eq!(run(1), 1);
+    assert_eq!(
+        buffer.take(),
+        "\u{1b}[36mrun(n: 1)\u{1b}[0m
+\u{1b}[32m├─ \u{1b}[0m\u{1b}[32mleaf()\u{1b}[0m
+\u{1b}[32m│  \u{1b}[0m\u{1b}[32mleaf = ()\u{1b}[0m
+\u{1b}[32m└─ \u{1b}[0m\u{1b}[32mfib(n: 1)\u{1b}[0m
+\u{1b}[32m   \u{1b}[0m\u{1b}[32mfib = 1\u{1b}[0m
+\u{1b}[36mrun = 1\u{1b}[0m
+"
+    );
+
+    trace2::set_format(trace2::Format::default());
+}
@@ -0,0 +1,175 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+
+struct Engine;
+
+#[::trace2::trace2]
+impl Engine {
+    fn get(&self, id: u32) -> u32 {
+        self.read(id)
+    }
+
+    fn read(&self, id: u32) -> u32 {
+        self.decode(id)
+    }
+
+    fn decode(&self, id: u32) -> u32 {
+        id + 1
+    }
+}
+
+#[::trace2::trace2(trigger)]
+impl Engine {
+    fn commit(&self, id: u32) -> u32 {
+        self.get(id) + 1
+    }
+}
+
+#[::trace2::trace2(trigger, max_depth = 1)]
+impl Engine {
+    fn flush(&self, id: u32) -> u32 {
+        self.get(id)
+    }
+}
+
+#[::trace2::trace2]
+fn serve(engine: &Engine) -> u32 {
+    engine.commit(1)
+}
+
+#[::trace2::trace2]
+fn switch(engine: &Engine) -> u32 {
+    trace2::set_trigger_only(true);
+    engine.commit(1)
+}
+
+impl std::fmt::Debug for Engine {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Engine")
+    }
+}
+
+#[test]
+fn test_trigger() {
+    let engine = Engine;
+    trace2::assert_trace!(
+        engine.get(1),
+        r#"
+        Engine::get(id: 1)
+          Engine::read(id: 1)
+            Engine::decode(id: 1)
+            Engine::decode = 2
+          Engine::read = 2
+        Engine::get = 2
+        "#
+    );
+
+    // Calling a trigger function doesn't enable trigger-only mode.
+    engine.commit(1);
+    trace2::assert_trace!(
+        engine.get(2),
+        r#"
+        Engine::get(id: 2)
+          Engine::read(id: 2)
+            Engine::decode(id: 2)
+            Engine::decode = 3
+          Engine::read = 3
+        Engine::get = 3
+        "#
+    );
+
+    trace2::set_trigger_only(true);
+    trace2::assert_trace!(
+        engine.commit(1),
+        r#"
+        Engine::commit(id: 1)
+          Engine::get(id: 1)
+            Engine::read(id: 1)
+              Engine::decode(id: 1)
+              Engine::decode = 2
+            Engine::read = 2
+          Engine::get = 2
+        Engine::commit = 3
+        "#
+    );
+    trace2::assert_trace!(engine.get(1), "");
+    trace2::assert_trace!(
+        engine.flush(1),
+        r#"
+        Engine::flush(id: 1)
+          Engine::get(id: 1)
+          Engine::get = 2
+        Engine::flush = 2
+        "#
+    );
+    trace2::set_trigger_only(false);
+
+    // Tests share the mode and the sink, so the rest runs in the same test.
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+    trace2::set_format(trace2::Format {
+        indent: trace2::Indent::Tree { colors: false },
+        ..trace2::Format::default()
+    });
+
+    // A trigger call beneath a traced call is drawn as a tree written when the caller returns.
+    assert_eq!(serve(&engine), 3);
+    assert_eq!(
+        buffer.take(),
+        "\
+trigger::serve(engine: Engine)
+└─ trigger::Engine::commit(id: 1)
+   └─ trigger::Engine::get(id: 1)
+      └─ trigger::Engine::read(id: 1)
+         └─ trigger::Engine::decode(id: 1)
+            trigger::Engine::decode = 2
+         trigger::Engine::read = 2
+      trigger::Engine::get = 2
+   trigger::Engine::commit = 3
+trigger::serve = 3
+"
+    );
+
+    // Calls that have started when the mode is changed keep their exit records.
+    assert_eq!(switch(&engine), 3);
+    assert_eq!(
+        buffer.take(),
+        "\
+trigger::switch(engine: Engine)
+└─ trigger::Engine::commit(id: 1)
+   └─ trigger::Engine::get(id: 1)
+      └─ trigger::Engine::read(id: 1)
+         └─ trigger::Engine::decode(id: 1)
+            trigger::Engine::decode = 2
+         trigger::Engine::read = 2
+      trigger::Engine::get = 2
+   trigger::Engine::commit = 3
+trigger::switch = 3
+"
+    );
+
+    // Only the trigger call is written once the mode applies to the outermost call.
+    assert_eq!(serve(&engine), 3);
+    assert_eq!(
+        buffer.take(),
+        "\
+trigger::Engine::commit(id: 1)
+└─ trigger::Engine::get(id: 1)
+   └─ trigger::Engine::read(id: 1)
+      └─ trigger::Engine::decode(id: 1)
+         trigger::Engine::decode = 2
+      trigger::Engine::read = 2
+   trigger::Engine::get = 2
+trigger::Engine::commit = 3
+"
+    );
+
+    trace2::set_trigger_only(false);
+    trace2::set_format(trace2::Format::default());
+}
@@ -0,0 +1,129 @@
+#![feature(use_extern_macros)]
+#![feature(proc_macro_path_invoc)]
+
+extern crate trace2;
+
+mod common;
+
+use common::SharedBuffer;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::thread;
+
+#[::trace2::trace2]
+fn work(n: u32) -> u32 {
+    if n == 0 {
+        0
+    } else {
+        work(n - 1) + n
+    }
+}
+
+/// A value whose `Debug` implementation calls a traced function.
+struct Nested;
+
+impl fmt::Debug for Nested {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Nested({})", work(0))
+    }
+}
+
+#[::trace2::trace2]
+fn show(_v: Nested) {}
+
+#[::trace2::trace2(trigger)]
+fn triggered() {}
+
+/// A sink kept to be flushed, with the buffer it writes to.
+struct Handle(trace2::WriterSink, SharedBuffer);
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("handle")
+    }
+}
+
+#[::trace2::trace2]
+fn flushed(handle: &Handle) -> String {
+    handle.0.flush();
+    handle.1.take()
+}
+
+#[::trace2::trace2]
+fn quiet(handle: &Handle) -> String {
+    triggered();
+    handle.1.take()
+}
+
+#[test]
+fn test_writer() {
+    let buffer = SharedBuffer::default();
+    trace2::set_sink(trace2::WriterSink::new(buffer.clone()));
+
+    let handles = (0..4)
+        .map(|_| thread::spawn(|| work(10)))
+        .collect::<Vec<_>>();
+    for handle in handles {
+        assert_eq!(handle.join().unwrap(), 55);
+    }
+
+    // The whole call tree of each thread is written at once.
+    let output = buffer.take();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4 * 22);
+    for tree in lines.chunks(22) {
+        assert_eq!(tree[0], ">>>> writer::work(n: 10)");
+        assert_eq!(
+            tree[10],
+            ">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>> writer::work(n: 0)"
+        );
+        assert_eq!(tree[21], "<<<< writer::work = 55");
+    }
+
+    // Records of calls made while formatting values are written before the record.
+    show(Nested);
+    assert_eq!(
+        buffer.take(),
+        ">>>>>>>> writer::work(n: 0)
+<<<<<<<< writer::work = 0
+>>>> writer::show(_v: Nested(0))
+<<<< writer::show = ()
+"
+    );
+
+    // A clone of the sink writes lines of the current call before it returns.
+    let sink = trace2::WriterSink::new(buffer.clone());
+    trace2::set_sink(sink.clone());
+    let handle = Handle(sink, buffer.clone());
+    assert_eq!(flushed(&handle), ">>>> writer::flushed(handle: handle)\n");
+    assert_eq!(
+        buffer.take(),
+        r#"<<<< writer::flushed = ">>>> writer::flushed(handle: handle)\n"
+"#
+    );
+
+    // Lines are written when the outermost call with records returns, even if it is beneath
+    // other traced calls.
+    trace2::set_trigger_only(true);
+    assert_eq!(
+        quiet(&handle),
+        ">>>>>>>> writer::triggered()\n<<<<<<<< writer::triggered = ()\n"
+    );
+    trace2::set_trigger_only(false);
+    assert_eq!(buffer.take(), "");
+
+    let path = env::temp_dir().join(format!("trace2-writer-{}.log", std::process::id()));
+    let _ = fs::remove_file(&path);
+    trace2::init_file(&path).unwrap();
+    assert_eq!(work(1), 1);
+    trace2::init_stderr();
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        ">>>> writer::work(n: 1)\n\
+         >>>>>>>> writer::work(n: 0)\n\
+         <<<<<<<< writer::work = 0\n\
+         <<<< writer::work = 1\n"
+    );
+    fs::remove_file(&path).unwrap();
+}
@@ -2,11 +2,30 @@ use proc_macro2::Span;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::ToTokens;
 use syn;
+use syn::spanned::Spanned;
+
+use super::config::{Config, EmitOn};
 
 pub struct Codegen;
 
+/// A named value to be printed in a trace record.
+struct PrintableValue {
+    name: String,
+    value: TokenStream2,
+
+    /// Names checked against `redact(..)` and redaction patterns, i.e. the name of the value and
+    /// names of struct fields it is nested in.
+    redact_names: Vec<String>,
+}
+
 impl Codegen {
-    fn extract_printable_args<'a>(pat: &'a syn::Pat, extract_target: &mut Vec<&'a syn::Ident>) {
+    /// Extract named bindings from an argument pattern, with names of struct fields they are
+    /// nested in. A binding is redacted when its own name or any of the field names is redacted.
+    fn extract_printable_args<'a>(
+        pat: &'a syn::Pat,
+        fields: &[String],
+        extract_target: &mut Vec<(&'a syn::Ident, Vec<String>)>,
+    ) {
         match pat {
             syn::Pat::Wild(_) => {
                 // ignore args without a name
@@ -19,53 +38,54 @@ impl Codegen {
             | syn::Pat::Verbatim(_)
             | syn::Pat::Macro(_) => panic!("Unexpected argument pattern: {:?}", pat),
             syn::Pat::Ident(ref ident) => {
-                if ident.ident.to_string() != "self" {
-                    extract_target.push(&ident.ident);
+                let name = ident.ident.to_string();
+                if name != "self" {
+                    let mut names = vec![name];
+                    names.extend(fields.iter().cloned());
+                    extract_target.push((&ident.ident, names));
                 }
             }
             syn::Pat::TupleStruct(ref tuple_struct) => {
                 for pat in tuple_struct.pat.front.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
                 for pat in tuple_struct.pat.back.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
             }
             syn::Pat::Tuple(ref tuple) => {
                 for pat in tuple.front.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
                 for pat in tuple.back.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
             }
             syn::Pat::Struct(ref structure) => {
                 for pat in structure.fields.iter() {
-                    Self::extract_printable_args(&*pat.pat, extract_target);
+                    let mut fields = fields.to_vec();
+                    if let syn::Member::Named(ref ident) = pat.member {
+                        fields.push(ident.to_string());
+                    }
+                    Self::extract_printable_args(&pat.pat, &fields, extract_target);
                 }
             }
             syn::Pat::Slice(ref slice) => {
                 for pat in slice.front.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
                 if let Some(ref pat) = slice.middle {
-                    Self::extract_printable_args(&*pat, extract_target);
+                    Self::extract_printable_args(&*pat, fields, extract_target);
                 }
                 for pat in slice.back.iter() {
-                    Self::extract_printable_args(pat, extract_target);
+                    Self::extract_printable_args(pat, fields, extract_target);
                 }
             }
         }
     }
 
-    /// Build begin trace statement.
-    ///
-    /// Output sample:
-    ///
-    /// ```ignore
-    /// trace!("{} {}::foo(arg1: {:?}, arg2: {:?})", ">".repeat(..), module_path!(), arg1, arg2);
-    /// ```
-    fn build_begin_trace_statement(fn_decl: &syn::FnDecl, fn_name: &str) -> TokenStream2 {
+    /// Extract named bindings from all arguments except the receiver.
+    fn extract_fn_args(fn_decl: &syn::FnDecl) -> Vec<(&syn::Ident, Vec<String>)> {
         let mut args = vec![];
         for fn_arg in fn_decl.inputs.iter() {
             match fn_arg {
@@ -73,42 +93,305 @@ impl Codegen {
                     // ignore self arg
                 }
                 syn::FnArg::Captured(ref arg) => {
-                    Self::extract_printable_args(&arg.pat, &mut args);
+                    Self::extract_printable_args(&arg.pat, &[], &mut args);
                 }
                 syn::FnArg::Inferred(ref arg_pat) => {
-                    Self::extract_printable_args(arg_pat, &mut args);
+                    Self::extract_printable_args(arg_pat, &[], &mut args);
                 }
                 syn::FnArg::Ignored(_) => {
                     // ignore ignored arg
                 }
             }
         }
+        args
+    }
+
+    /// Whether the function takes the receiver by reference, i.e. `&self`, `&mut self` or
+    /// `self: &Self`.
+    fn has_self_ref(fn_decl: &syn::FnDecl) -> bool {
+        fn_decl.inputs.iter().any(|fn_arg| match fn_arg {
+            syn::FnArg::SelfRef(_) => true,
+            syn::FnArg::Captured(ref arg) => match arg.pat {
+                syn::Pat::Ident(ref ident) => ident.ident == "self" && Self::is_copy_type(&arg.ty),
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// Whether the function has a receiver, i.e. `self`, `&self`, `&mut self` or `self: Box<Self>`.
+    fn has_self_arg(fn_decl: &syn::FnDecl) -> bool {
+        fn_decl.inputs.iter().any(|fn_arg| match fn_arg {
+            syn::FnArg::SelfRef(_) | syn::FnArg::SelfValue(_) => true,
+            syn::FnArg::Captured(ref arg) => match arg.pat {
+                syn::Pat::Ident(ref ident) => ident.ident.to_string() == "self",
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    /// Collect bindings listed in `after(..)`. Bindings that do not exist in the function
+    /// signature are skipped, so that the option can be applied to a whole impl or mod. Bindings
+    /// that may be moved into the function body are skipped too, i.e. receivers taken by value and
+    /// arguments taken by value unless their types are known to be `Copy`, see `is_copy_type`.
+    fn build_after_args(fn_decl: &syn::FnDecl, config: &Config) -> Vec<PrintableValue> {
+        let mut args = vec![];
+        for fn_arg in fn_decl.inputs.iter() {
+            if let syn::FnArg::Captured(ref arg) = *fn_arg {
+                if Self::is_copy_type(&arg.ty) {
+                    Self::extract_printable_args(&arg.pat, &[], &mut args);
+                }
+            }
+        }
+
+        let mut after_args = vec![];
+        for name in config.after.iter() {
+            if name == "self" {
+                if Self::has_self_ref(fn_decl) {
+                    after_args.push(PrintableValue {
+                        name: name.clone(),
+                        value: quote!(self),
+                        redact_names: vec![name.clone()],
+                    });
+                }
+            } else if let Some(&(ident, ref redact_names)) =
+                args.iter().find(|(ident, _)| ident.to_string() == *name)
+            {
+                after_args.push(PrintableValue {
+                    name: name.clone(),
+                    value: ident.into_token_stream(),
+                    redact_names: redact_names.clone(),
+                });
+            }
+        }
+        after_args
+    }
+
+    /// Whether bindings of a type can be used after they are moved, i.e. references and types
+    /// known to be `Copy` by their names, like `u32` or `(bool, char)`. References are included,
+    /// since `&mut` arguments are reborrowed rather than moved when passed to other functions.
+    fn is_copy_type(ty: &syn::Type) -> bool {
+        const COPY_TYPES: &[&str] = &[
+            "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16",
+            "u32", "u64", "u128", "usize",
+        ];
+        match *ty {
+            syn::Type::Reference(_) | syn::Type::Ptr(_) | syn::Type::Never(_) => true,
+            syn::Type::Paren(ref ty) => Self::is_copy_type(&ty.elem),
+            syn::Type::Group(ref ty) => Self::is_copy_type(&ty.elem),
+            syn::Type::Array(ref ty) => Self::is_copy_type(&ty.elem),
+            syn::Type::Tuple(ref ty) => ty.elems.iter().all(Self::is_copy_type),
+            syn::Type::Path(ref ty) => {
+                ty.qself.is_none()
+                    && ty.path.segments.len() == 1
+                    && COPY_TYPES
+                        .iter()
+                        .any(|name| ty.path.segments[0].ident == name)
+            }
+            _ => false,
+        }
+    }
+
+    /// Build a value to be printed. Values listed in `redact(..)` are printed as `***`. Others
+    /// are checked against redaction patterns by the runtime, and truncated according to
+    /// `max_len`.
+    fn build_printable_value(value: &PrintableValue, config: &Config) -> TokenStream2 {
+        if config.is_redacted(&value.redact_names) {
+            return quote!(trace2::Redacted);
+        }
+        let names = &value.redact_names;
+        let value = &value.value;
+        let value = match config.redact_patterns {
+            Some(ref patterns) => quote! {
+                trace2::Redact::with_patterns(&[#(#names),*], &[#(#patterns),*], &(#value))
+            },
+            None => quote!(trace2::Redact::new(&[#(#names),*], &(#value))),
+        };
+        Self::build_truncated_value(&value, config)
+    }
+
+    /// Wrap a value to be printed, so that its output is truncated according to `max_len`.
+    fn build_truncated_value(value: &TokenStream2, config: &Config) -> TokenStream2 {
+        let max_len = match config.max_len {
+            Some(max_len) => quote!(Some(#max_len)),
+            None => quote!(trace2::max_len()),
+        };
+        quote!(trace2::Truncated::new(&(#value), #max_len))
+    }
 
-        let format_args = args
+    /// Build `trace2::NamedValue`s for a list of printable values, like `("a", &a)`.
+    fn build_named_values(values: &[PrintableValue], config: &Config) -> Vec<TokenStream2> {
+        values
             .iter()
-            .map(|arg_ident| format!("{}: {{:?}}", arg_ident))
-            .collect::<Vec<_>>()
-            .join(", ");
+            .map(|value| {
+                let name = &value.name;
+                let value = Self::build_printable_value(value, config);
+                quote!((#name, &#value))
+            })
+            .collect()
+    }
 
-        let format = format!("{{}} {{}}::{}({})", fn_name, format_args);
+    /// Build the list of values printed in the entry record, i.e. the receiver with `self` or
+    /// `self_fields(..)` options, arguments, and then `fields(..)`.
+    fn build_entry_values(fn_decl: &syn::FnDecl, config: &Config) -> Vec<PrintableValue> {
+        // The receiver is only printed when it is explicitly asked for.
+        let mut values = vec![];
+        if Self::has_self_arg(fn_decl) {
+            if config.print_self {
+                values.push(PrintableValue {
+                    name: "self".to_owned(),
+                    value: quote!(self),
+                    redact_names: vec!["self".to_owned()],
+                });
+            }
+            for field in config.self_fields.iter() {
+                let ident = syn::Ident::new(field, Span::call_site());
+                values.push(PrintableValue {
+                    name: format!("self.{}", field),
+                    value: quote!(self.#ident),
+                    redact_names: vec![field.clone()],
+                });
+            }
+        }
+        for (arg, redact_names) in Self::extract_fn_args(fn_decl) {
+            values.push(PrintableValue {
+                name: arg.to_string(),
+                value: arg.into_token_stream(),
+                redact_names,
+            });
+        }
+        for field in config.fields.iter() {
+            let name = field.name.to_string();
+            let expr = &field.expr;
+            values.push(PrintableValue {
+                redact_names: vec![name.clone()],
+                name,
+                value: quote!(#expr),
+            });
+        }
+        values
+    }
+
+    /// Build begin trace statements. The record is rendered by the runtime according to its
+    /// format.
+    ///
+    /// Output sample:
+    ///
+    /// ```ignore
+    /// // The call is left when the guard is dropped, even if the function panics.
+    /// let __call = trace2::enter(module_path!(), "foo");
+    /// trace2::emit(&trace2::Record::enter(&__call, &[("arg1", &arg1), ("arg2", &arg2)]));
+    /// ```
+    ///
+    /// With `self` or `self_fields(..)` options, the receiver is printed before arguments:
+    ///
+    /// ```ignore
+    /// trace2::emit(&trace2::Record::enter(&__call, &[("self.state", &self.state), ("arg1", &arg1)]));
+    /// ```
+    ///
+    /// With the `fold_recursion` option, the call is entered as a fold before `__call`, so that
+    /// records of direct recursive calls are hidden:
+    ///
+    /// ```ignore
+    /// let __fold = trace2::enter_fold(module_path!(), "foo");
+    /// ```
+    ///
+    /// With the `recursion_limit` option, the entry record is also passed to the runtime to count
+    /// calls of the function:
+    ///
+    /// ```ignore
+    /// let __call = trace2::enter(module_path!(), "foo");
+    /// let __recursion = match trace2::Record::enter(&__call, &[("arg1", &arg1)]) {
+    ///     __record => {
+    ///         trace2::emit(&__record);
+    ///         trace2::enter_recursion_limit(&__record, 500)
+    ///     }
+    /// };
+    /// ```
+    fn build_begin_trace_statement(
+        fn_name: &str,
+        entry_values: &[PrintableValue],
+        locations: &TokenStream2,
+        config: &Config,
+    ) -> TokenStream2 {
+        let values = Self::build_named_values(entry_values, config);
+        let fold = if config.fold_recursion {
+            quote!(let __fold = trace2::enter_fold(module_path!(), #fn_name);)
+        } else {
+            quote!()
+        };
+
+        let emit = match config.recursion_limit {
+            Some(limit) => quote! {
+                // Temporaries of the scrutinee, i.e. formatted values, live until the end of the
+                // statement.
+                let __recursion = match trace2::Record::enter(&__call, &[#(#values),*])#locations {
+                    __record => {
+                        trace2::emit(&__record);
+                        trace2::enter_recursion_limit(&__record, #limit)
+                    }
+                };
+            },
+            None => quote! {
+                trace2::emit(&trace2::Record::enter(&__call, &[#(#values),*])#locations);
+            },
+        };
 
         quote! {
-            trace!(#format, ">".repeat(__level * 4), module_path!(), #(#args),*)
+            #fold
+            // The call is left when the guard is dropped, even if the function panics.
+            let __call = trace2::enter(module_path!(), #fn_name);
+            #emit
+        }
+    }
+
+    /// Build the list of values printed in the exit record besides the return value,
+    /// i.e. `after(..)` bindings followed by `exit_fields(..)`.
+    fn build_exit_values(fn_decl: &syn::FnDecl, config: &Config) -> Vec<PrintableValue> {
+        let mut values = Self::build_after_args(fn_decl, config);
+        for field in config.exit_fields.iter() {
+            let name = field.name.to_string();
+            let expr = &field.expr;
+            values.push(PrintableValue {
+                redact_names: vec![name.clone()],
+                name,
+                value: quote!(#expr),
+            });
         }
+        values
     }
 
-    /// Build end trace statement.
+    /// Build end trace statements. The record is rendered by the runtime according to its format.
     ///
     /// Output sample:
     ///
     /// ```ignore
-    /// trace!("{} {}::foo = {:?}", "<".repeat(..), module_path!(), __ret);
+    /// trace2::emit(&trace2::Record::exit(&__call, &[], &__ret));
     /// ```
-    fn build_end_trace_statement(fn_name: &str) -> TokenStream2 {
-        let format = format!("{{}} {{}}::{} = {{:?}}", fn_name);
+    ///
+    /// When there are `after(..)` bindings or `exit_fields(..)`, they are passed like arguments:
+    ///
+    /// ```ignore
+    /// trace2::emit(&trace2::Record::exit(&__call, &[("buf", &buf)], &__ret));
+    /// ```
+    ///
+    /// With the `fold_recursion` option, `.with_recursion(&__fold)` is appended to the record.
+    fn build_end_trace_statement(
+        exit_values: &[PrintableValue],
+        locations: &TokenStream2,
+        config: &Config,
+    ) -> TokenStream2 {
+        let values = Self::build_named_values(exit_values, config);
+        let ret = Self::build_truncated_value(&quote!(__ret), config);
+        let recursion = if config.fold_recursion {
+            quote!(.with_recursion(&__fold))
+        } else {
+            quote!()
+        };
 
         quote! {
-            trace!(#format, "<".repeat(__level * 4), module_path!(), __ret)
+            trace2::emit(&trace2::Record::exit(&__call, &[#(#values),*], &#ret)#locations #recursion);
         }
     }
 
@@ -130,6 +413,123 @@ impl Codegen {
         }
     }
 
+    /// Build the statement that marks the call as a trigger or limits the depth of calls beneath
+    /// it, according to the `trigger` and `max_depth` options.
+    ///
+    /// Output sample:
+    ///
+    /// ```ignore
+    /// let __trigger = trace2::enter_trigger(Some(3));
+    /// ```
+    ///
+    /// or, without `trigger`:
+    ///
+    /// ```ignore
+    /// let __max_depth = trace2::enter_max_depth(3);
+    /// ```
+    fn build_trigger_statement(config: &Config) -> TokenStream2 {
+        if !config.trigger {
+            return match config.max_depth {
+                Some(max_depth) => quote!(let __max_depth = trace2::enter_max_depth(#max_depth);),
+                None => quote!(),
+            };
+        }
+        let max_depth = match config.max_depth {
+            Some(max_depth) => quote!(Some(#max_depth)),
+            None => quote!(None),
+        };
+        quote!(let __trigger = trace2::enter_trigger(#max_depth);)
+    }
+
+    /// Build the statements that buffer records of a root call and emit them if it fails, according
+    /// to the `root` option.
+    ///
+    /// Output sample:
+    ///
+    /// ```ignore
+    /// let __root = trace2::enter_root();
+    /// ...
+    /// __root.finish(trace2::Failure::is_failure(&__ret));
+    /// ```
+    ///
+    /// With `on = "err"`, functions returning `()`, `bool` or `Option<..>` get a compile error
+    /// pointing to the return type, since they cannot tell whether a call fails.
+    fn build_root_statements(
+        decl: &syn::FnDecl,
+        ident: &syn::Ident,
+        config: &Config,
+    ) -> (TokenStream2, TokenStream2) {
+        let failed = match config.root {
+            None => return (quote!(), quote!()),
+            Some(EmitOn::Err) => match Self::build_failure_error(decl, ident) {
+                Some(error) => error,
+                None => quote!(trace2::Failure::is_failure(&__ret)),
+            },
+            Some(EmitOn::Panic) => quote!(false),
+        };
+        (
+            quote!(let __root = trace2::enter_root();),
+            quote!(__root.finish(#failed);),
+        )
+    }
+
+    /// Build a compile error if the return type cannot tell whether a call fails for
+    /// `root, on = "err"`, i.e. `()`, `bool` or `Option<..>`. Other types are expected to implement
+    /// `trace2::Failure`.
+    fn build_failure_error(decl: &syn::FnDecl, ident: &syn::Ident) -> Option<TokenStream2> {
+        let span = match decl.output {
+            syn::ReturnType::Default => ident.span(),
+            syn::ReturnType::Type(_, ref ty) => match **ty {
+                syn::Type::Tuple(ref tuple) if tuple.elems.is_empty() => tuple.span(),
+                syn::Type::Path(ref path)
+                    if path.qself.is_none()
+                        && path.path.segments.last().into_iter().any(|segment| {
+                            let segment = segment.value();
+                            segment.ident == "bool" || segment.ident == "Option"
+                        }) =>
+                {
+                    path.span()
+                }
+                _ => return None,
+            },
+        };
+        let message = format!(
+            "`root` emits records when `{}` returns `Err`, but it does not return a `Result`; \
+             use `root, on = \"panic\"` to emit records when it panics",
+            ident
+        );
+        Some(quote_spanned!(span=> compile_error!(#message)))
+    }
+
+    /// Build the methods that attach locations to records, according to `location` and `caller`
+    /// options. The location of the function is given by the span of its name.
+    ///
+    /// Output sample:
+    ///
+    /// ```ignore
+    /// .with_location(file!(), line!()).with_caller(__caller)
+    /// ```
+    fn build_record_locations(ident: &syn::Ident, config: &Config) -> TokenStream2 {
+        let mut locations = quote!();
+        if config.location {
+            locations.extend(quote_spanned!(ident.span()=> .with_location(file!(), line!())));
+        }
+        if config.caller {
+            locations.extend(quote!(.with_caller(__caller)));
+        }
+        locations
+    }
+
+    /// Build attributes to be added to a traced function, i.e. `#[track_caller]` with the `caller`
+    /// option.
+    pub fn build_fn_attrs(config: &Config) -> Vec<syn::Attribute> {
+        if config.caller {
+            vec![parse_quote!(#[track_caller])]
+        } else {
+            vec![]
+        }
+    }
+
     /// Transform and build a function block.
     ///
     /// Suppose we receive:
@@ -142,7 +542,8 @@ impl Codegen {
     /// This function will transform it into:
     /// ```ignore
     /// (pub) fn foo<T>(&self, arg1: T, arg2: foo) -> bool where T: bar {
-    ///     trace!("{} foo(arg1: {:?}, arg2: {:?})", ">".repeat(..), arg1, arg2);
+    ///     let __call = trace2::enter(module_path!(), "foo");
+    ///     trace2::emit(&trace2::Record::enter(&__call, &[("arg1", &arg1), ("arg2", &arg2)]));
     ///     let mut __inner = move || {
     ///         let __inner_ret: bool = {
     ///             ...
@@ -151,11 +552,29 @@ impl Codegen {
     ///         __inner_ret
     ///     };
     ///     let __ret = __inner();
-    ///     trace!("{} foo = {:?}", ">".repeat(..), __ret);
+    ///     trace2::emit(&trace2::Record::exit(&__call, &[], &__ret));
     ///     __ret
     /// }
     /// ```
-    pub fn build_block(decl: &syn::FnDecl, ident: &syn::Ident, impl_type: Option<&syn::Type>, block: &syn::Block) -> TokenStream2 {
+    ///
+    /// If there are `after(..)` bindings or `exit_fields(..)`, `__inner` is not a `move` closure,
+    /// so that arguments can still be accessed when building the end trace statement.
+    ///
+    /// With the `caller` option, the call site is saved in `__caller` before anything else, since
+    /// `Location::caller()` only works outside of the closure.
+    ///
+    /// With the `root` option, records are buffered from before the entry record until after the
+    /// exit record, see `build_root_statements`. `__root` is declared before `__call`, so that the
+    /// record emitted when `__call` is dropped during unwinding is buffered too. Likewise, with the
+    /// `trigger` or `max_depth` option, `__trigger` or `__max_depth` is declared before them, see
+    /// `build_trigger_statement`.
+    pub fn build_block(
+        decl: &syn::FnDecl,
+        ident: &syn::Ident,
+        impl_type: Option<&syn::Type>,
+        block: &syn::Block,
+        config: &Config,
+    ) -> TokenStream2 {
         let fn_name = {
             let impl_type_str = match impl_type {
                 None => "".to_owned(),
@@ -166,19 +585,33 @@ impl Codegen {
             format!("{}{}", impl_type_str, ident)
         };
 
-        let begin_trace = Self::build_begin_trace_statement(decl, &fn_name);
-        let end_trace = Self::build_end_trace_statement(&fn_name);
+        let entry_values = Self::build_entry_values(decl, config);
+        let exit_values = Self::build_exit_values(decl, config);
+        let locations = Self::build_record_locations(ident, config);
+        let trigger = Self::build_trigger_statement(config);
+        let (begin_root, end_root) = Self::build_root_statements(decl, ident, config);
+        let begin_trace =
+            Self::build_begin_trace_statement(&fn_name, &entry_values, &locations, config);
+        let end_trace = Self::build_end_trace_statement(&exit_values, &locations, config);
+        let caller = if config.caller {
+            quote!(let __caller = ::std::panic::Location::caller();)
+        } else {
+            quote!()
+        };
         let return_type = Self::build_return_type(decl);
+        let capture = if exit_values.is_empty() {
+            quote!(move)
+        } else {
+            quote!()
+        };
         quote! {
             {
                 use trace2;
-                trace2::FUNC_CALL_LEVEL.with(|level| {
-                    let mut __level = level.get();
-                    __level = __level.saturating_add(1);
-                    level.set(__level);
-                    #begin_trace;
-                });
-                let mut __inner = move || {
+                #caller
+                #trigger
+                #begin_root
+                #begin_trace
+                let mut __inner = #capture || {
                     // Explicitly give types, so that Box<..> can be correctly inferred.
                     let __inner_ret: #return_type = #block;
 
@@ -187,14 +620,76 @@ impl Codegen {
                     __inner_ret
                 };
                 let __ret = __inner();
-                trace2::FUNC_CALL_LEVEL.with(|level| {
-                    let mut __level = level.get();
-                    #end_trace;
-                    __level = __level.saturating_sub(1);
-                    level.set(__level);
-                });
+                #end_trace
+                #end_root
                 __ret
             }
         }
     }
+
+    /// Transform a test function for `#[trace2::test]`.
+    ///
+    /// Suppose we receive:
+    /// ```ignore
+    /// fn foo() -> Result<(), Error> {
+    ///     ...
+    /// }
+    /// ```
+    ///
+    /// This function will transform it into:
+    /// ```ignore
+    /// #[test]
+    /// fn foo() -> Result<(), Error> {
+    ///     trace2::__run_test(|| {
+    ///         let __inner_ret: Result<(), Error> = {
+    ///             ...
+    ///         };
+    ///         #[allow(unreachable_code)]
+    ///         __inner_ret
+    ///     })
+    /// }
+    /// ```
+    ///
+    /// `__run_test` captures records of the test, and prints them only if the test panics or
+    /// returns `Err`.
+    pub fn build_test_fn(mut item: syn::ItemFn) -> syn::ItemFn {
+        let return_type = Self::build_return_type(&item.decl);
+        let block = &item.block;
+        let new_block = quote! {
+            {
+                use trace2;
+                trace2::__run_test(|| {
+                    let __inner_ret: #return_type = #block;
+
+                    #[allow(unreachable_code)]
+                    __inner_ret
+                })
+            }
+        };
+        item.block = Box::new(syn::parse2(new_block).unwrap());
+        item.attrs.insert(0, parse_quote!(#[test]));
+        item
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Codegen;
+    use syn;
+
+    fn has_failure_error(item: &str) -> bool {
+        let item = syn::parse_str::<syn::ItemFn>(item).unwrap();
+        Codegen::build_failure_error(&item.decl, &item.ident).is_some()
+    }
+
+    #[test]
+    fn failure_error() {
+        assert!(has_failure_error("fn foo() {}"));
+        assert!(has_failure_error("fn foo() -> () {}"));
+        assert!(has_failure_error("fn foo() -> bool { true }"));
+        assert!(has_failure_error("fn foo() -> Option<u32> { None }"));
+        assert!(!has_failure_error("fn foo() -> Result<u32, ()> { Ok(1) }"));
+        assert!(!has_failure_error("fn foo() -> io::Result<()> { Ok(()) }"));
+        assert!(!has_failure_error("fn foo() -> Status { Status::Ok }"));
+    }
 }
@@ -1,24 +1,186 @@
+use proc_macro2::TokenStream as TokenStream2;
 use syn;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Config {
     pub ignore: bool,
+
+    /// Bindings to be printed again in the exit record, e.g. `after(buf, self)`.
+    pub after: Vec<String>,
+
+    /// Whether the receiver should be printed in the entry record, i.e. `self`.
+    pub print_self: bool,
+
+    /// Fields of the receiver to be printed in the entry record, e.g. `self_fields(state, id)`.
+    pub self_fields: Vec<String>,
+
+    /// Extra computed fields appended to the entry record, e.g. `fields(n = items.len())`.
+    pub fields: Vec<Field>,
+
+    /// Extra computed fields appended to the exit record, e.g. `exit_fields(n = items.len())`.
+    pub exit_fields: Vec<Field>,
+
+    /// Maximum length in bytes of each formatted value, e.g. `max_len = 256`. When it is not
+    /// specified, the global setting of the runtime is used.
+    pub max_len: Option<usize>,
+
+    /// Names of bindings whose values are printed as `***`, e.g. `redact(password, token)`.
+    pub redact: Vec<String>,
+
+    /// Name patterns of bindings whose values are printed as `***`, e.g.
+    /// `redact_patterns("*secret*", "*_key")`. `*` matches any characters. Names are matched by
+    /// the runtime, and `trace2::DEFAULT_REDACT_PATTERNS` is used when it is `None`.
+    pub redact_patterns: Option<Vec<String>>,
+
+    /// Whether the source location of the function is printed, i.e. `location`.
+    pub location: bool,
+
+    /// Whether the location of the call site is printed, i.e. `caller`. The function is then
+    /// marked as `#[track_caller]`.
+    pub caller: bool,
+
+    /// When records of the call and calls beneath it are emitted, if they are buffered until the
+    /// call returns, e.g. `root, on = "err"`. `None` if the call is not a root.
+    pub root: Option<EmitOn>,
+
+    /// Whether records are emitted while the function is running, even if they are disabled by
+    /// `trace2::set_trigger_only`, i.e. `trigger`.
+    pub trigger: bool,
+
+    /// Maximum number of levels below the function whose records are emitted, e.g.
+    /// `max_depth = 3`.
+    pub max_depth: Option<usize>,
+
+    /// Whether direct recursive calls of the function are folded into the outermost call, i.e.
+    /// `fold_recursion`.
+    pub fold_recursion: bool,
+
+    /// Maximum number of calls of the function that have not returned in a thread, e.g.
+    /// `recursion_limit = 500`.
+    pub recursion_limit: Option<usize>,
+}
+
+/// When records of a root call are emitted, see `Config::root`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmitOn {
+    /// When the call returns `Err` or panics, i.e. `on = "err"`, which is the default.
+    Err,
+
+    /// When the call panics, i.e. `on = "panic"`.
+    Panic,
 }
 
 impl Config {
     fn from_raw_pairs(pairs: Vec<RawConfigPair>) -> Config {
         let mut config = Config::default();
+        let mut root = false;
+        let mut on = None;
         for pair in pairs {
             match pair.name.to_string().as_str() {
                 "ignore" => {
-                    assert!(pair.value.is_none());
+                    assert!(pair.value.is_none() && pair.args.is_none());
                     config.ignore = true;
                 }
+                "self" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    config.print_self = true;
+                }
+                "location" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    config.location = true;
+                }
+                "caller" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    config.caller = true;
+                }
+                "trigger" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    config.trigger = true;
+                }
+                "fold_recursion" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    config.fold_recursion = true;
+                }
+                "root" => {
+                    assert!(pair.value.is_none() && pair.args.is_none());
+                    root = true;
+                }
+                "on" => match pair.value {
+                    Some(syn::Lit::Str(ref v)) if v.value() == "err" => on = Some(EmitOn::Err),
+                    Some(syn::Lit::Str(ref v)) if v.value() == "panic" => on = Some(EmitOn::Panic),
+                    _ => panic!("Configuration on expects \"err\" or \"panic\", like on = \"err\""),
+                },
+                "self_fields" => {
+                    config.self_fields = pair
+                        .parse_args::<syn::Ident>()
+                        .into_iter()
+                        .map(|ident| ident.to_string())
+                        .collect();
+                }
+                "fields" => {
+                    config.fields = pair.parse_args::<Field>();
+                }
+                "exit_fields" => {
+                    config.exit_fields = pair.parse_args::<Field>();
+                }
+                "max_len" => match pair.value {
+                    Some(syn::Lit::Int(ref v)) => config.max_len = Some(v.value() as usize),
+                    _ => panic!("Configuration max_len expects an integer, like max_len = 256"),
+                },
+                "max_depth" => match pair.value {
+                    Some(syn::Lit::Int(ref v)) => config.max_depth = Some(v.value() as usize),
+                    _ => panic!("Configuration max_depth expects an integer, like max_depth = 3"),
+                },
+                "recursion_limit" => match pair.value {
+                    Some(syn::Lit::Int(ref v)) => {
+                        config.recursion_limit = Some(v.value() as usize)
+                    }
+                    _ => panic!(
+                        "Configuration recursion_limit expects an integer, like recursion_limit = 500"
+                    ),
+                },
+                "redact" => {
+                    config.redact = pair
+                        .parse_args::<syn::Ident>()
+                        .into_iter()
+                        .map(|ident| ident.to_string())
+                        .collect();
+                }
+                "redact_patterns" => {
+                    config.redact_patterns = Some(
+                        pair.parse_args::<syn::LitStr>()
+                            .into_iter()
+                            .map(|lit| lit.value())
+                            .collect(),
+                    );
+                }
+                "after" => {
+                    config.after = pair
+                        .parse_args::<BindingName>()
+                        .into_iter()
+                        .map(|b| b.0)
+                        .collect();
+                }
                 _ => panic!("Unexpected configuration: {}", pair.name),
             }
         }
+        if root {
+            config.root = Some(on.unwrap_or(EmitOn::Err));
+        } else if on.is_some() {
+            panic!("Configuration on can only be used with root");
+        }
         config
     }
+
+    /// Whether a binding or field with one of the given names is listed in `redact(..)`, so that
+    /// it is printed as `***` without checking name patterns.
+    pub fn is_redacted<S: AsRef<str>>(&self, names: &[S]) -> bool {
+        names.iter().any(|name| {
+            self.redact
+                .iter()
+                .any(|n| n.to_lowercase() == name.as_ref().to_lowercase())
+        })
+    }
 }
 
 impl syn::synom::Synom for Config {
@@ -29,21 +191,36 @@ impl syn::synom::Synom for Config {
     }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Config { ignore: false }
-    }
-}
-
 #[derive(Debug)]
 struct RawConfigPair {
     name: syn::Ident,
     value: Option<syn::Lit>,
+    args: Option<TokenStream2>,
+}
+
+impl RawConfigPair {
+    /// Parses the parenthesized arguments of a list configuration like `after(a, b)`.
+    fn parse_args<T: syn::synom::Synom>(&self) -> Vec<T> {
+        assert!(self.value.is_none());
+        let args = self.args.clone().unwrap_or_else(|| {
+            panic!(
+                "Configuration {} expects a list, like {}(..)",
+                self.name, self.name
+            )
+        });
+        syn::parse2::<List<T>>(args)
+            .unwrap_or_else(|_| panic!("Failed to parse arguments of configuration {}", self.name))
+            .0
+    }
 }
 
 impl syn::synom::Synom for RawConfigPair {
     named!(parse -> Self, do_parse!(
-        name: syn!(syn::Ident) >>
+        name: alt!(
+            syn!(syn::Ident)
+            |
+            keyword!(self) => { |token| syn::Ident::new("self", token.0) }
+        ) >>
         value: option!(
             do_parse!(
                 punct!(=) >>
@@ -51,16 +228,59 @@ impl syn::synom::Synom for RawConfigPair {
                 (v)
             )
         ) >>
+        args: option!(map!(parens!(syn!(TokenStream2)), |p| p.1)) >>
         (RawConfigPair {
             name,
             value,
+            args,
+        })
+    ));
+}
+
+/// A comma separated list, e.g. the `a, b` in `after(a, b)`.
+struct List<T>(Vec<T>);
+
+impl<T: syn::synom::Synom> syn::synom::Synom for List<T> {
+    fn parse(cursor: syn::buffer::Cursor) -> syn::synom::PResult<Self> {
+        let result = syn::punctuated::Punctuated::<T, Token![,]>::parse_terminated(cursor);
+        result.map(|(items, cursor)| (List(items.into_iter().collect()), cursor))
+    }
+}
+
+/// A computed field in the form of `name = expr`.
+#[derive(Debug)]
+pub struct Field {
+    pub name: syn::Ident,
+    pub expr: syn::Expr,
+}
+
+impl syn::synom::Synom for Field {
+    named!(parse -> Self, do_parse!(
+        name: syn!(syn::Ident) >>
+        punct!(=) >>
+        expr: syn!(syn::Expr) >>
+        (Field {
+            name,
+            expr,
         })
     ));
 }
 
+/// Name of an argument binding. Unlike `syn::Ident`, `self` is accepted.
+#[derive(Debug)]
+struct BindingName(String);
+
+impl syn::synom::Synom for BindingName {
+    named!(parse -> Self, alt!(
+        syn!(syn::Ident) => { |ident| BindingName(ident.to_string()) }
+        |
+        keyword!(self) => { |_| BindingName("self".to_owned()) }
+    ));
+}
+
 #[cfg(test)]
 mod test {
-    use super::{Config, RawConfigPair};
+    use super::{Config, EmitOn, RawConfigPair};
 
     use syn;
 
@@ -79,6 +299,15 @@ mod test {
         assert_eq!(pair.name, "boz");
         let value = pair.value.unwrap();
         assert_eq!(quote!(#value).to_string(), "123");
+
+        let pair = syn::parse_str::<RawConfigPair>("after(a, self)").unwrap();
+        assert_eq!(pair.name, "after");
+        assert_eq!(pair.value, None);
+        assert_eq!(pair.args.unwrap().to_string(), "a , self");
+
+        let pair = syn::parse_str::<RawConfigPair>("self").unwrap();
+        assert_eq!(pair.name, "self");
+        assert_eq!(pair.value, None);
     }
 
     #[test]
@@ -88,5 +317,57 @@ mod test {
 
         let config = syn::parse_str::<Config>("").unwrap();
         assert_eq!(config.ignore, false);
+        assert!(config.after.is_empty());
+
+        let config = syn::parse_str::<Config>("after(buf, self)").unwrap();
+        assert_eq!(config.after, vec!["buf", "self"]);
+
+        let config = syn::parse_str::<Config>("self, self_fields(state, id)").unwrap();
+        assert_eq!(config.print_self, true);
+        assert_eq!(config.self_fields, vec!["state", "id"]);
+
+        let config =
+            syn::parse_str::<Config>("fields(n = items.len(), id = req.id), exit_fields(n = v[0])")
+                .unwrap();
+        assert_eq!(config.fields.len(), 2);
+        assert_eq!(config.fields[0].name, "n");
+        let expr = &config.fields[0].expr;
+        assert_eq!(quote!(#expr).to_string(), "items . len ( )");
+        assert_eq!(config.fields[1].name, "id");
+        assert_eq!(config.exit_fields.len(), 1);
+
+        let config = syn::parse_str::<Config>("max_len = 256").unwrap();
+        assert_eq!(config.max_len, Some(256));
+
+        let config = syn::parse_str::<Config>("location, caller").unwrap();
+        assert_eq!(config.location, true);
+        assert_eq!(config.caller, true);
+        assert_eq!(config.root, None);
+
+        let config = syn::parse_str::<Config>("root").unwrap();
+        assert_eq!(config.root, Some(EmitOn::Err));
+        let config = syn::parse_str::<Config>("on = \"panic\", root").unwrap();
+        assert_eq!(config.root, Some(EmitOn::Panic));
+
+        let config = syn::parse_str::<Config>("trigger, max_depth = 3").unwrap();
+        assert_eq!(config.trigger, true);
+        assert_eq!(config.max_depth, Some(3));
+        let config = syn::parse_str::<Config>("fold_recursion").unwrap();
+        assert_eq!(config.fold_recursion, true);
+        let config = syn::parse_str::<Config>("recursion_limit = 500").unwrap();
+        assert_eq!(config.recursion_limit, Some(500));
+        let config = syn::parse_str::<Config>("max_depth = 20").unwrap();
+        assert_eq!(config.trigger, false);
+        assert_eq!(config.max_depth, Some(20));
+
+        let config = syn::parse_str::<Config>("").unwrap();
+        assert_eq!(config.redact_patterns, None);
+        assert!(!config.is_redacted(&["password"]));
+
+        let config = syn::parse_str::<Config>("redact(pin), redact_patterns(\"*cred*\")").unwrap();
+        assert!(config.is_redacted(&["pin"]));
+        assert!(config.is_redacted(&["code", "PIN"]));
+        assert!(!config.is_redacted(&["pincode"]));
+        assert_eq!(config.redact_patterns, Some(vec!["*cred*".to_owned()]));
     }
 }
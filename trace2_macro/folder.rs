@@ -27,13 +27,18 @@ pub struct Folder {
     /// The current impl block's type. It is set when entering a `impl` block,
     /// and unset when existing.
     current_impl: Option<syn::Type>,
+
+    /// The configuration of the `#[trace2]` attribute where fold begins. It applies to all
+    /// functions traced by this folder.
+    config: Config,
 }
 
 impl Folder {
-    fn new(scope: FoldScope) -> Self {
+    fn new(scope: FoldScope, config: Config) -> Self {
         Self {
             scope,
             current_impl: None,
+            config,
         }
     }
 }
@@ -48,7 +53,7 @@ impl Folder {
         // Try to parse as `mod {}`
         let body = syn::parse2::<syn::ItemMod>(input.clone());
         if let Ok(body) = body {
-            return Folder::new(FoldScope::Mod)
+            return Folder::new(FoldScope::Mod, config)
                 .fold_item_mod(body)
                 .into_token_stream();
         }
@@ -56,7 +61,7 @@ impl Folder {
         // Try to parse as `fn()`
         let body = syn::parse2::<syn::ItemFn>(input.clone());
         if let Ok(body) = body {
-            return Folder::new(FoldScope::Fn)
+            return Folder::new(FoldScope::Fn, config)
                 .fold_item_fn(body)
                 .into_token_stream();
         }
@@ -64,7 +69,7 @@ impl Folder {
         // Try to parse as `impl {}`
         let body = syn::parse2::<syn::ItemImpl>(input.clone());
         if let Ok(body) = body {
-            return Folder::new(FoldScope::Impl)
+            return Folder::new(FoldScope::Impl, config)
                 .fold_item_impl(body)
                 .into_token_stream();
         }
@@ -102,9 +107,16 @@ impl Fold for Folder {
                 return i;
             }
         }
-        let new_block_tokens = Codegen::build_block(&i.sig.decl, &i.sig.ident, self.current_impl.as_ref(), &i.block);
+        let new_block_tokens = Codegen::build_block(
+            &i.sig.decl,
+            &i.sig.ident,
+            self.current_impl.as_ref(),
+            &i.block,
+            &self.config,
+        );
         let new_block = syn::parse2(new_block_tokens).unwrap();
         i.block = new_block;
+        i.attrs.extend(Codegen::build_fn_attrs(&self.config));
         i
     }
 
@@ -116,9 +128,11 @@ impl Fold for Folder {
                 return i;
             }
         }
-        let new_block_tokens = Codegen::build_block(&*i.decl, &i.ident, None, &*i.block);
+        let new_block_tokens =
+            Codegen::build_block(&*i.decl, &i.ident, None, &*i.block, &self.config);
         let new_block = syn::parse2(new_block_tokens).unwrap();
         i.block = Box::new(new_block);
+        i.attrs.extend(Codegen::build_fn_attrs(&self.config));
         i
     }
 
@@ -172,7 +186,6 @@ impl Fold for TypeFolder {
     }
 }
 
-
 #[derive(Debug)]
 struct AttrTTS(Config);
 
@@ -7,6 +7,8 @@ extern crate syn;
 #[macro_use]
 extern crate quote;
 
+use quote::ToTokens;
+
 mod codegen;
 mod config;
 mod folder;
@@ -18,3 +20,18 @@ pub fn trace2(
 ) -> proc_macro::TokenStream {
     folder::Folder::fold(args.into(), input.into()).into()
 }
+
+#[proc_macro_attribute]
+pub fn test(
+    args: proc_macro::TokenStream,
+    input: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    if !args.is_empty() {
+        panic!("`#[trace2::test]` does not accept options");
+    }
+    let item = syn::parse::<syn::ItemFn>(input)
+        .expect("Invalid attribute position, `#[trace2::test]` only supports function.");
+    codegen::Codegen::build_test_fn(item)
+        .into_token_stream()
+        .into()
+}